@@ -0,0 +1,242 @@
+use crate::{
+    config::Config,
+    org::{OrgMetadata, OrgMetadataType},
+    util::headline_href,
+};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use orgize::elements::{Timestamp, Title};
+use serde::Serialize;
+use std::fs;
+
+// -- Agenda ---------------------------------------------------------------------
+//
+// Every headline with one of the site's `file.todo_keywords`, from every public
+// file, with its priority, tags and planning. Available to every layout as
+// `agenda`, and written to a page grouped by keyword and then date, if the site
+// has an agenda layout.
+
+/// AgendaItem is what a headline's keyword line and planning say about it;
+/// it is collected as an OrgMetadata entry, like a file's clocks and tags.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgendaItem {
+    pub keyword: String,
+    pub priority: Option<char>,
+    pub tags: Vec<String>,
+    /// YYYY-MM-DD, with HH:MM if the timestamp has a time.
+    pub scheduled: Option<String>,
+    pub deadline: Option<String>,
+}
+
+/// timestamp_str formats the start of a timestamp, sortably.
+fn timestamp_str(ts: &Timestamp) -> Option<String> {
+    let start = match ts {
+        Timestamp::Active { start, .. }
+        | Timestamp::Inactive { start, .. }
+        | Timestamp::ActiveRange { start, .. }
+        | Timestamp::InactiveRange { start, .. } => start,
+        Timestamp::Diary { .. } => return None,
+    };
+    let dt: NaiveDateTime = start.into();
+    let fmt = if start.hour.is_some() {
+        "%Y-%m-%d %H:%M"
+    } else {
+        "%Y-%m-%d"
+    };
+    Some(dt.format(fmt).to_string())
+}
+
+impl AgendaItem {
+    /// from_title is the agenda item for a headline, if it has a keyword.
+    pub fn from_title(title: &Title) -> Option<AgendaItem> {
+        let keyword = title.keyword.as_ref()?.to_string();
+        let planning = title.planning.as_ref();
+        Some(AgendaItem {
+            keyword,
+            priority: title.priority,
+            tags: title.tags.iter().map(|t| t.to_string()).collect(),
+            scheduled: planning
+                .and_then(|p| p.scheduled.as_ref())
+                .and_then(timestamp_str),
+            deadline: planning
+                .and_then(|p| p.deadline.as_ref())
+                .and_then(timestamp_str),
+        })
+    }
+
+    /// date is the day the item is planned for: when it's scheduled, or else due.
+    pub fn date(&self) -> Option<&str> {
+        self.scheduled
+            .as_ref()
+            .or(self.deadline.as_ref())
+            .map(|d| &d[..10])
+    }
+}
+
+/// AgendaEntry is an agenda item and where it came from, as given to templates.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AgendaEntry {
+    #[serde(flatten)]
+    pub item: AgendaItem,
+    pub date: Option<String>,
+    pub headline: String,
+    pub file: String,
+    /// the headline's url.
+    pub path: String,
+}
+
+/// AgendaDate is the entries for a keyword planned on one day (or not planned).
+#[derive(Debug, Serialize)]
+struct AgendaDate<'a> {
+    date: Option<&'a str>,
+    items: Vec<&'a AgendaEntry>,
+}
+
+/// AgendaGroup is the entries for one keyword.
+#[derive(Debug, Serialize)]
+struct AgendaGroup<'a> {
+    keyword: &'a str,
+    dates: Vec<AgendaDate<'a>>,
+}
+
+/// collect turns the agenda metadata into entries, in `todo_keywords` order,
+/// then by date (unplanned last).
+pub fn collect(
+    agenda: &[OrgMetadata],
+    todo_keywords: &[String],
+    base_url: &str,
+) -> Vec<AgendaEntry> {
+    let mut out: Vec<AgendaEntry> = agenda
+        .iter()
+        .filter_map(|m| match &m.entity {
+            OrgMetadataType::Agenda(item) => {
+                let headline = m.originating_headline.clone().unwrap_or_default();
+                let file_url = format!("{}/{}", base_url, m.get_web_path_as_str());
+                Some(AgendaEntry {
+                    item: item.clone(),
+                    date: item.date().map(|d| d.to_string()),
+                    path: headline_href(&file_url, &headline),
+                    headline,
+                    file: m.originating_file.clone(),
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    out.sort_by_key(|e| {
+        (
+            todo_keywords.iter().position(|k| *k == e.item.keyword),
+            e.date.is_none(),
+            e.date.clone(),
+            e.file.clone(),
+        )
+    });
+    out
+}
+
+/// group splits the (sorted) entries by keyword, and then by date.
+fn group(entries: &[AgendaEntry]) -> Vec<AgendaGroup<'_>> {
+    let mut groups: Vec<AgendaGroup> = Vec::new();
+    for entry in entries {
+        if groups.last().map(|g| g.keyword) != Some(&entry.item.keyword[..]) {
+            groups.push(AgendaGroup {
+                keyword: &entry.item.keyword,
+                dates: Vec::new(),
+            });
+        }
+        let dates = &mut groups.last_mut().expect("a group was pushed").dates;
+        match dates.last_mut() {
+            Some(d) if d.date == entry.date.as_deref() => d.items.push(entry),
+            _ => dates.push(AgendaDate {
+                date: entry.date.as_deref(),
+                items: vec![entry],
+            }),
+        }
+    }
+    groups
+}
+
+/// build renders the agenda page with the agenda layout, if the site has one.
+pub fn build(cfg: &Config) -> Result<()> {
+    let agenda_cfg = &cfg.user_config.agenda;
+    let layout = format!("{}.html", agenda_cfg.layout);
+    if !cfg.tera.get_template_names().any(|t| t == layout) {
+        return Ok(());
+    }
+
+    let mut ctx = cfg.site_context.clone();
+    ctx.insert("title", "Agenda");
+    ctx.insert("agenda", &cfg.agenda);
+    ctx.insert("groups", &group(&cfg.agenda));
+
+    let output = cfg
+        .tera
+        .render(&layout, &ctx)
+        .context("Failed to render agenda layout")?;
+    let out_path = cfg.dir_site_out.join(&agenda_cfg.path);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create agenda directory")?;
+    }
+    fs::write(&out_path, output).context("Failed to write agenda page")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{front_matter::FrontMatter, org::OrgFile};
+    use orgize::{Org, ParseConfig};
+    use std::path::Path;
+
+    #[test]
+    fn test_agenda() {
+        let keywords = vec![
+            "TODO".to_string(),
+            "WAITING".to_string(),
+            "DONE".to_string(),
+        ];
+        let parsed = Org::parse_custom(
+            "* DONE [#A] Shipped :work:\nCLOSED: [2022-01-01 Sat]\n* TODO Later\n* WAITING On review\nDEADLINE: <2022-02-01 Tue>\n* TODO [#B] Soon :work:home:\nSCHEDULED: <2022-01-10 Mon 09:30> DEADLINE: <2022-01-12 Wed>\n* Plain headline\n",
+            &ParseConfig {
+                todo_keywords: (keywords.clone(), vec![]),
+            },
+        );
+        let (_, _, _, agenda, _) = OrgFile::collect_data(
+            &parsed,
+            Path::new("tasks.html"),
+            Path::new("tasks.org"),
+            FrontMatter {
+                title: Some("Tasks".to_string()),
+                ..FrontMatter::default()
+            },
+        );
+        let entries = collect(&agenda, &keywords, "https://x.com");
+
+        let order: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|e| (&e.item.keyword[..], &e.headline[..]))
+            .collect();
+        assert_eq!(
+            vec![
+                ("TODO", "Soon"),
+                ("TODO", "Later"),
+                ("WAITING", "On review"),
+                ("DONE", "Shipped")
+            ],
+            order
+        );
+        let soon = &entries[0];
+        assert_eq!(Some('B'), soon.item.priority);
+        assert_eq!(vec!["work", "home"], soon.item.tags);
+        assert_eq!(Some("2022-01-10 09:30".to_string()), soon.item.scheduled);
+        assert_eq!(Some("2022-01-12".to_string()), soon.item.deadline);
+        assert_eq!(Some("2022-01-10".to_string()), soon.date);
+        assert_eq!("https://x.com/tasks.html#Soon", soon.path);
+        assert_eq!(Some("2022-02-01".to_string()), entries[2].date);
+
+        let groups = group(&entries);
+        assert_eq!(3, groups.len());
+        assert_eq!(2, groups[0].dates.len());
+        assert_eq!(None, groups[0].dates[1].date);
+    }
+}
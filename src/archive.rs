@@ -0,0 +1,236 @@
+use crate::{config::Config, templates::links::LinkData};
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDateTime};
+use serde::Serialize;
+use std::fs;
+
+// -- Archive --------------------------------------------------------------------
+//
+// Every file in the sitemap with a `#+date_created` (so, neither private nor
+// `firn_sitemap: false`), grouped by the year and month it was created, and
+// written to <archive.url><year>/index.html and <archive.url><year>/<month>/index.html.
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// ArchiveMonth is a month's entry in the `archive` available to every layout.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveMonth {
+    pub month: u32,
+    pub name: String,
+    pub count: usize,
+    pub path: String,
+}
+
+/// ArchiveYear is a year's entry in the `archive`, newest month first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ArchiveYear {
+    pub year: i32,
+    pub count: usize,
+    pub path: String,
+    pub months: Vec<ArchiveMonth>,
+}
+
+/// ArchiveGroup is a month of items, as given to the archive layout.
+#[derive(Debug, Serialize)]
+struct ArchiveGroup<'a> {
+    title: String,
+    path: String,
+    items: Vec<&'a LinkData>,
+}
+
+/// created is an item's (`date_created_ts`, year, month), from its front matter.
+fn created(item: &LinkData) -> Option<(i64, i32, u32)> {
+    let front_matter = item.front_matter.as_ref()?;
+    let ts = front_matter.date_created_ts?;
+    let month = NaiveDateTime::from_timestamp_opt(ts, 0)?.month();
+    Some((ts, front_matter.date_created_year?, month))
+}
+
+/// by_month groups the dated items by (year, month), newest first.
+fn by_month(sitemap: &[LinkData]) -> Vec<(i32, u32, Vec<&LinkData>)> {
+    let mut dated: Vec<((i64, i32, u32), &LinkData)> = sitemap
+        .iter()
+        .filter_map(|item| created(item).map(|date| (date, item)))
+        .collect();
+    dated.sort_by(|((a_ts, ..), a), ((b_ts, ..), b)| {
+        b_ts.cmp(a_ts).then_with(|| a.file.cmp(&b.file))
+    });
+
+    let mut out: Vec<(i32, u32, Vec<&LinkData>)> = Vec::new();
+    for ((_, year, month), item) in dated {
+        match out.last_mut() {
+            Some((y, m, items)) if *y == year && *m == month => items.push(item),
+            _ => out.push((year, month, vec![item])),
+        }
+    }
+    out
+}
+
+fn year_web_path(archive_url: &str, year: i32) -> String {
+    format!("{}{}/index.html", archive_url, year)
+}
+
+fn month_web_path(archive_url: &str, year: i32, month: u32) -> String {
+    format!("{}{}/{:02}/index.html", archive_url, year, month)
+}
+
+/// summary counts the archive's items per year and month, for sidebars.
+pub fn summary(sitemap: &[LinkData], base_url: &str, archive_url: &str) -> Vec<ArchiveYear> {
+    let mut years: Vec<ArchiveYear> = Vec::new();
+    for (year, month, items) in by_month(sitemap) {
+        let archive_month = ArchiveMonth {
+            month,
+            name: MONTHS[month as usize - 1].to_string(),
+            count: items.len(),
+            path: format!("{}/{}", base_url, month_web_path(archive_url, year, month)),
+        };
+        match years.last_mut() {
+            Some(y) if y.year == year => {
+                y.count += items.len();
+                y.months.push(archive_month);
+            }
+            _ => years.push(ArchiveYear {
+                year,
+                count: items.len(),
+                path: format!("{}/{}", base_url, year_web_path(archive_url, year)),
+                months: vec![archive_month],
+            }),
+        }
+    }
+    years
+}
+
+fn write_page(cfg: &Config, layout: &str, web_path: &str, ctx: &tera::Context) -> Result<()> {
+    let output = cfg
+        .tera
+        .render(layout, ctx)
+        .context("Failed to render archive layout")?;
+    let out_path = cfg.dir_site_out.join(web_path);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create archive directory")?;
+    }
+    fs::write(&out_path, output).context("Failed to write archive page")
+}
+
+/// build renders a page for every year and month in the archive with the
+/// archive layout, if the site has one.
+pub fn build(cfg: &Config) -> Result<()> {
+    let archive_cfg = &cfg.user_config.archive;
+    let layout = format!("{}.html", archive_cfg.layout);
+    if !cfg.tera.get_template_names().any(|t| t == layout) {
+        return Ok(());
+    }
+
+    let groups: Vec<(i32, ArchiveGroup)> = by_month(&cfg.sitemap)
+        .into_iter()
+        .map(|(year, month, items)| {
+            let group = ArchiveGroup {
+                title: format!("{} {}", MONTHS[month as usize - 1], year),
+                path: format!(
+                    "{}/{}",
+                    cfg.user_config.site.url,
+                    month_web_path(&archive_cfg.url, year, month)
+                ),
+                items,
+            };
+            (year, group)
+        })
+        .collect();
+
+    let mut ctx = cfg.site_context.clone();
+
+    for archive_year in &cfg.archive {
+        let year = archive_year.year;
+        let months: Vec<&ArchiveGroup> = groups
+            .iter()
+            .filter(|(y, _)| *y == year)
+            .map(|(_, group)| group)
+            .collect();
+        let items: Vec<&LinkData> = months.iter().flat_map(|g| g.items.clone()).collect();
+
+        ctx.insert("title", &year.to_string());
+        ctx.insert("year", &year);
+        ctx.insert("month", &None::<u32>);
+        ctx.insert("items", &items);
+        ctx.insert("groups", &months);
+        write_page(cfg, &layout, &year_web_path(&archive_cfg.url, year), &ctx)?;
+
+        for (archive_month, group) in archive_year.months.iter().zip(&months) {
+            ctx.insert("title", &group.title);
+            ctx.insert("month", &archive_month.month);
+            ctx.insert("items", &group.items);
+            ctx.insert("groups", &[group]);
+            let web_path = month_web_path(&archive_cfg.url, year, archive_month.month);
+            write_page(cfg, &layout, &web_path, &ctx)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{front_matter::FrontMatter, templates::links::LinkMeta};
+    use chrono::NaiveDate;
+
+    fn entry(title: &str, date: Option<&str>) -> LinkData {
+        let date = date.map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap());
+        let front_matter = FrontMatter {
+            title: Some(title.to_string()),
+            date_created: date.map(|d| d.to_string()),
+            date_created_ts: date.map(|d| d.and_hms(0, 0, 0).timestamp()),
+            date_created_year: date.map(|d| d.year()),
+            ..FrontMatter::default()
+        };
+        LinkData::new(
+            format!("/{}.html", title),
+            title.to_string(),
+            LinkMeta::Sitemap,
+            Some(front_matter),
+        )
+    }
+
+    #[test]
+    fn test_summary() {
+        let sitemap = vec![
+            entry("a", Some("2021-12-01")),
+            entry("b", Some("2022-02-10")),
+            entry("undated", None),
+            entry("c", Some("2022-02-01")),
+            entry("d", Some("2022-01-15")),
+        ];
+        let grouped = by_month(&sitemap);
+        let files: Vec<Vec<&str>> = grouped
+            .iter()
+            .map(|(_, _, items)| items.iter().map(|i| &i.file[..]).collect())
+            .collect();
+        assert_eq!(vec![vec!["b", "c"], vec!["d"], vec!["a"]], files);
+
+        let archive = summary(&sitemap, "https://x.com", "archive/");
+        assert_eq!(2, archive.len());
+        assert_eq!(2022, archive[0].year);
+        assert_eq!(3, archive[0].count);
+        assert_eq!("https://x.com/archive/2022/index.html", archive[0].path);
+        assert_eq!("February", archive[0].months[0].name);
+        assert_eq!(2, archive[0].months[0].count);
+        assert_eq!(
+            "https://x.com/archive/2022/02/index.html",
+            archive[0].months[0].path
+        );
+        assert_eq!(1, archive[1].count);
+    }
+}
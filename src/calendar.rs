@@ -0,0 +1,369 @@
+use crate::{
+    config::Config,
+    org::OrgFile,
+    util::{self, headline_href},
+};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use orgize::elements::{Datetime, Timestamp};
+use orgize::{Element, Event, Org};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// -- Calendar -------------------------------------------------------------------
+//
+// An iCalendar (.ics) file of every SCHEDULED and DEADLINE headline, and every
+// active timestamp, in the public files, for subscribing to from a calendar app.
+// Events link back to their headline, and org repeaters (ex: `+1w`) become RRULEs.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub summary: String,
+    pub url: String,
+    /// "SCHEDULED", "DEADLINE", or None for a timestamp in the text.
+    pub kind: Option<String>,
+    pub start: NaiveDateTime,
+    pub end: Option<NaiveDateTime>,
+    /// timestamps without a time are all day events.
+    pub all_day: bool,
+    pub rrule: Option<String>,
+    pub categories: Vec<String>,
+}
+
+/// rrule turns an org repeater (`+1w`, `++2d`, `.+1m`) into an RRULE value.
+/// NOTE: orgize 0.9 doesn't parse repeaters yet (a timestamp with one isn't
+/// parsed as a timestamp at all), so this only kicks in once it does.
+fn rrule(repeater: &str) -> Option<String> {
+    let value = repeater.trim_start_matches(['+', '.']);
+    let (interval, unit) = value.split_at(value.len().checked_sub(1)?);
+    let freq = match unit {
+        "h" => "HOURLY",
+        "d" => "DAILY",
+        "w" => "WEEKLY",
+        "m" => "MONTHLY",
+        "y" => "YEARLY",
+        _ => return None,
+    };
+    let interval: u32 = interval.parse().ok()?;
+    Some(format!("FREQ={};INTERVAL={}", freq, interval))
+}
+
+/// active_span is when an active timestamp starts (and ends), and its repeater.
+fn active_span<'t>(
+    ts: &'t Timestamp,
+) -> Option<(&'t Datetime<'t>, Option<&'t Datetime<'t>>, Option<&'t str>)> {
+    match ts {
+        Timestamp::Active {
+            start, repeater, ..
+        } => Some((start, None, repeater.as_deref())),
+        Timestamp::ActiveRange {
+            start,
+            end,
+            repeater,
+            ..
+        } => Some((start, Some(end), repeater.as_deref())),
+        _ => None,
+    }
+}
+
+struct Headline {
+    title: String,
+    tags: Vec<String>,
+    url: String,
+}
+
+/// file_events finds the events in a parsed file. Events before the first
+/// headline are named after the file.
+pub fn file_events(
+    parsed: &Org,
+    file_url: &str,
+    file_title: &str,
+    file_tags: &[String],
+) -> Vec<CalendarEvent> {
+    let mut out: Vec<CalendarEvent> = Vec::new();
+    let mut headline = Headline {
+        title: file_title.to_string(),
+        tags: vec![],
+        url: file_url.to_string(),
+    };
+
+    let mut push = |headline: &Headline, kind: Option<&str>, ts: &Timestamp| {
+        let (start, end, repeater) = match active_span(ts) {
+            Some(span) => span,
+            None => return,
+        };
+        let mut categories = file_tags.to_vec();
+        categories.extend(headline.tags.iter().cloned());
+        let all_day = start.hour.is_none();
+        let start: NaiveDateTime = start.into();
+        // the uid is made from the event itself, so that adding or removing other
+        // events doesn't change it, which would duplicate it in subscribed calendars.
+        let mut uid = format!(
+            "{}/{}-{}",
+            headline.url,
+            kind.unwrap_or("event").to_lowercase(),
+            start.format("%Y%m%dT%H%M%S")
+        );
+        let same = out.iter().filter(|e| e.uid.starts_with(&uid)).count();
+        if same > 0 {
+            uid = format!("{}-{}", uid, same + 1);
+        }
+        out.push(CalendarEvent {
+            uid,
+            summary: headline.title.clone(),
+            url: headline.url.clone(),
+            kind: kind.map(|k| k.to_string()),
+            start,
+            end: end.map(|e| e.into()),
+            all_day,
+            rrule: repeater.and_then(rrule),
+            categories,
+        })
+    };
+
+    for event in parsed.iter() {
+        match event {
+            Event::Start(Element::Title(title)) => {
+                headline = Headline {
+                    title: title.raw.to_string(),
+                    tags: title.tags.iter().map(|t| t.to_string()).collect(),
+                    url: headline_href(file_url, &title.raw),
+                };
+                if let Some(planning) = &title.planning {
+                    if let Some(ts) = &planning.scheduled {
+                        push(&headline, Some("SCHEDULED"), ts);
+                    }
+                    if let Some(ts) = &planning.deadline {
+                        push(&headline, Some("DEADLINE"), ts);
+                    }
+                }
+            }
+            Event::Start(Element::Timestamp(ts)) => push(&headline, None, ts),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// escape escapes an iCalendar TEXT value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// fold splits a content line into lines of at most 75 octets, as iCalendar requires.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+fn format_dt(dt: &NaiveDateTime, all_day: bool) -> String {
+    if all_day {
+        format!(";VALUE=DATE:{}", dt.format("%Y%m%d"))
+    } else {
+        format!(":{}", dt.format("%Y%m%dT%H%M%S"))
+    }
+}
+
+/// to_ics writes the events as a calendar; `dtstamp` is when it was written.
+pub fn to_ics(name: &str, events: &[CalendarEvent], dtstamp: &str) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//The Ice Shelf//Firn//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", dtstamp));
+        lines.push(format!("DTSTART{}", format_dt(&event.start, event.all_day)));
+        if let Some(end) = &event.end {
+            // an all day event ends (exclusively) the day after its last day.
+            let end = if event.all_day {
+                *end + chrono::Duration::days(1)
+            } else {
+                *end
+            };
+            lines.push(format!("DTEND{}", format_dt(&end, event.all_day)));
+        }
+        if let Some(rrule) = &event.rrule {
+            lines.push(format!("RRULE:{}", rrule));
+        }
+        lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+        if let Some(kind) = &event.kind {
+            lines.push(format!("DESCRIPTION:{}", escape(kind)));
+        }
+        lines.push(format!("URL:{}", event.url));
+        if !event.categories.is_empty() {
+            let categories: Vec<String> = event.categories.iter().map(|c| escape(c)).collect();
+            lines.push(format!("CATEGORIES:{}", categories.join(",")));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect()
+}
+
+fn write_calendar(out_path: PathBuf, contents: String) -> Result<()> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create calendar directory")?;
+    }
+    fs::write(&out_path, contents)
+        .with_context(|| format!("Failed to write calendar: {}", out_path.display()))
+}
+
+/// build writes the site's calendar, and optionally one per file or per tag.
+pub fn build(cfg: &Config) -> Result<()> {
+    let cal_cfg = &cfg.user_config.calendar;
+    if !cal_cfg.enabled {
+        return Ok(());
+    }
+    let site_title = &cfg.user_config.site.title;
+    let dtstamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    // private files and files in ignored directories are left out, as in collect_global_data.
+    let files: Vec<(&OrgFile, Vec<CalendarEvent>)> = cfg
+        .org_files
+        .iter()
+        .filter(|f| {
+            f.front_matter.title.is_some()
+                && !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+        })
+        .map(|f| {
+            let file_tags = f.front_matter.firn_tags.clone().unwrap_or_default();
+            let events = file_events(
+                &f.parsed,
+                &f.full_url,
+                f.front_matter.get_title(),
+                &file_tags,
+            );
+            (f, events)
+        })
+        .filter(|(_, events)| !events.is_empty())
+        .collect();
+
+    let all: Vec<CalendarEvent> = files.iter().flat_map(|(_, e)| e.clone()).collect();
+    write_calendar(
+        cfg.dir_site_out.join(&cal_cfg.path),
+        to_ics(site_title, &all, &dtstamp),
+    )?;
+
+    let split_dir = PathBuf::from(&cal_cfg.split_url);
+    match &cal_cfg.split[..] {
+        "file" => {
+            for (f, events) in &files {
+                let name = format!("{} - {}", site_title, f.front_matter.get_title());
+                write_calendar(
+                    cfg.dir_site_out
+                        .join(&split_dir)
+                        .join(f.web_path.with_extension("ics")),
+                    to_ics(&name, events, &dtstamp),
+                )?;
+            }
+        }
+        "tag" => {
+            let mut by_tag: BTreeMap<String, Vec<CalendarEvent>> = BTreeMap::new();
+            for event in &all {
+                for tag in &event.categories {
+                    let events = by_tag.entry(tag.to_lowercase()).or_default();
+                    if !events.contains(event) {
+                        events.push(event.clone());
+                    }
+                }
+            }
+            for (tag, events) in &by_tag {
+                let name = format!("{} - {}", site_title, tag);
+                write_calendar(
+                    cfg.dir_site_out
+                        .join(&split_dir)
+                        .join(format!("{}.ics", util::slugify(tag))),
+                    to_ics(&name, events, &dtstamp),
+                )?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rrule() {
+        assert_eq!(Some("FREQ=WEEKLY;INTERVAL=1".to_string()), rrule("+1w"));
+        assert_eq!(Some("FREQ=DAILY;INTERVAL=2".to_string()), rrule("++2d"));
+        assert_eq!(Some("FREQ=MONTHLY;INTERVAL=1".to_string()), rrule(".+1m"));
+        assert_eq!(None, rrule("+"));
+    }
+
+    #[test]
+    fn test_file_events() {
+        let parsed = Org::parse(
+            "Kickoff <2022-01-03 Mon>.\n* TODO Standup :work:\nSCHEDULED: <2022-01-10 Mon 09:30> DEADLINE: <2022-01-12 Wed>\n* Trip\nAway <2022-02-01 Tue>--<2022-02-03 Thu>, noted [2022-01-01 Sat].\n",
+        );
+        let events = file_events(
+            &parsed,
+            "https://x.com/p.html",
+            "Plans",
+            &["team".to_string()],
+        );
+        let summary: Vec<(&str, Option<&str>)> = events
+            .iter()
+            .map(|e| (&e.summary[..], e.kind.as_deref()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Plans", None),
+                ("Standup", Some("SCHEDULED")),
+                ("Standup", Some("DEADLINE")),
+                ("Trip", None)
+            ],
+            summary
+        );
+        let standup = &events[1];
+        assert!(!standup.all_day);
+        assert_eq!(None, standup.rrule);
+        assert_eq!("https://x.com/p.html#Standup", standup.url);
+        assert_eq!(
+            "https://x.com/p.html#Standup/scheduled-20220110T093000",
+            standup.uid
+        );
+        assert_eq!(vec!["team", "work"], standup.categories);
+        assert!(events[3].end.is_some());
+
+        let ics = to_ics("My, Site", &events, "20220101T000000Z");
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.contains("X-WR-CALNAME:My\\, Site\r\n"));
+        assert!(ics.contains("DTSTART:20220110T093000\r\nSUMMARY:Standup\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20220201\r\nDTEND;VALUE=DATE:20220204\r\n"));
+        assert!(ics.ends_with("END:VEVENT\r\nEND:VCALENDAR\r\n"));
+    }
+
+    #[test]
+    fn test_fold() {
+        let folded = fold(&"x".repeat(80));
+        assert_eq!(
+            format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(5)),
+            folded
+        );
+    }
+}
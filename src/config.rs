@@ -1,6 +1,17 @@
 use crate::{
+    agenda::{self, AgendaEntry},
+    archive::{self, ArchiveYear},
+    calendar,
+    crawlers,
     errors::{FirnError, FirnErrorType},
+    feeds,
+    gemini,
+    graph::{self, Graph},
     org::{self, OrgMetadata},
+    pagination,
+    search,
+    series,
+    sitemap_tree::{self, SitemapNode},
     templates::{self},
     templates::{
         data,
@@ -16,6 +27,7 @@ use rayon::prelude::*;
 use std::fs;
 use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::sync::Arc;
 use std::{collections::HashMap, fs::create_dir_all};
 use tera;
 
@@ -92,12 +104,22 @@ pub struct Config<'a> {
     pub dir_tags: PathBuf,
     pub dir_sass: PathBuf,
     pub serve_port: u16,
+    /// build `#+firn_draft` files too (`--drafts`); otherwise they're skipped entirely.
+    pub include_drafts: bool,
+    /// build files dated after `now` too (`--future`); otherwise they're skipped entirely.
+    pub include_future: bool,
+    /// the time the site is built for (`--now`), as a timestamp like `date_created_ts`;
+    /// without it, each build (and each rebuild under `firn serve`) uses the time it starts.
+    pub now: Option<i64>,
+    /// the drafts and scheduled files left out of the build, for the build report.
+    pub skipped_files: Vec<FirnError>,
     pub paths_org_files: Vec<PathBuf>,
     pub org_files: Vec<org::OrgFile<'a>>,
     pub global_tags: Vec<OrgMetadata<'a>>,
     pub global_sitemap: HashMap<String, OrgMetadata<'a>>,
     pub global_links: Vec<OrgMetadata<'a>>,
     pub global_logbook: Vec<OrgMetadata<'a>>,
+    pub global_agenda: Vec<OrgMetadata<'a>>,
     pub global_attachments: Vec<String>,
     pub tera: tera::Tera,
     pub verbosity: u8,
@@ -106,9 +128,22 @@ pub struct Config<'a> {
     pub sitemap: Vec<LinkData>,
     pub sitemap_mru: Vec<LinkData>,
     pub sitemap_mrp: Vec<LinkData>,
+    pub sitemap_tree: Vec<SitemapNode>,
+    /// problems with `#+firn_under` found while building the sitemap_tree.
+    pub sitemap_warnings: Vec<FirnError>,
     pub tag_page: PathBuf,
     pub tags_map: HashMap<String, Vec<OrgMetadata<'a>>>,
     pub tags_list: Vec<LinkData>,
+    pub posts: Vec<LinkData>,
+    pub archive: Vec<ArchiveYear>,
+    pub agenda: Vec<AgendaEntry>,
+    /// the site's clocks, for `logbook(site=true)` and the logbook page.
+    pub logbook: Arc<templates::Logbook>,
+    /// the files in each `#+firn_series` (by lowercased name), in order.
+    pub series: HashMap<String, Vec<LinkData>>,
+    pub graph: Graph,
+    /// the site-wide values every page's context starts from.
+    pub site_context: tera::Context,
     pub base_url: BaseUrl,
 }
 
@@ -160,11 +195,24 @@ impl<'a> Config<'a> {
             dir_tags,
             tag_page,
             tags_list: Vec::new(),
+            posts: Vec::new(),
+            archive: Vec::new(),
+            agenda: Vec::new(),
+            series: HashMap::new(),
+            logbook: Arc::default(),
             tags_map: HashMap::new(),
+            graph: Graph::default(),
+            site_context: tera::Context::new(),
             serve_port: 8080,
+            include_drafts: false,
+            include_future: false,
+            now: None,
+            skipped_files: Vec::new(),
             sitemap: Vec::new(),
             sitemap_mru: Vec::new(),
             sitemap_mrp: Vec::new(),
+            sitemap_tree: Vec::new(),
+            sitemap_warnings: Vec::new(),
             paths_org_files: Vec::new(),
             org_files: Vec::new(),
             verbosity,
@@ -173,6 +221,7 @@ impl<'a> Config<'a> {
             global_links: Vec::new(),
             global_sitemap: HashMap::new(),
             global_logbook: Vec::new(),
+            global_agenda: Vec::new(),
             user_config,
         })
     }
@@ -238,7 +287,7 @@ impl<'a> Config<'a> {
             .filter(|entry| {
                 !entry
                     .iter()
-                    .last()
+                    .next_back()
                     .map(|c| c.to_string_lossy().starts_with('_'))
                     .unwrap_or(true)
             })
@@ -257,7 +306,7 @@ impl<'a> Config<'a> {
                 .with_extension("css");
             // all parent dirs we need to recreate in static/css/<here>
             if out_path.parent().is_some() {
-                create_dir_all(&css_output_path.parent().unwrap())?;
+                create_dir_all(css_output_path.parent().unwrap())?;
             }
 
             // read file to string
@@ -290,12 +339,66 @@ impl<'a> Config<'a> {
             .collect();
 
         self.org_files = org_files;
+        self.skip_unpublished();
+    }
+
+    /// skip_unpublished leaves drafts, and files dated after `now`, out of the
+    /// build entirely, unless they were asked for with `--drafts` or `--future`.
+    fn skip_unpublished(&mut self) {
+        self.skipped_files.clear();
+        let (include_drafts, include_future) = (self.include_drafts, self.include_future);
+        let now = self
+            .now
+            .unwrap_or_else(|| chrono::Local::now().naive_local().timestamp());
+        let mut skipped = Vec::new();
+        self.org_files.retain(|f| {
+            if f.front_matter.firn_draft && !include_drafts {
+                skipped.push(FirnError::new(
+                    &format!("Draft: {}", f.file_path.display()),
+                    FirnErrorType::IsDraft,
+                ));
+                return false;
+            }
+            if !include_future && f.front_matter.date_created_ts.is_some_and(|ts| ts > now) {
+                skipped.push(FirnError::new(
+                    &format!(
+                        "Scheduled for {}: {}",
+                        f.front_matter.date_created.as_deref().unwrap_or_default(),
+                        f.file_path.display()
+                    ),
+                    FirnErrorType::IsScheduled,
+                ));
+                return false;
+            }
+            true
+        });
+        self.skipped_files = skipped;
     }
 
     pub fn clone_baseurl(&self) -> String {
         self.user_config.site.url.clone()
     }
 
+    /// public_posts returns every renderable, public `firn_type: post` file,
+    /// most recently created first. Posts without a `date_created` go last.
+    pub fn public_posts(&self) -> Vec<&org::OrgFile<'a>> {
+        let mut posts: Vec<_> = self
+            .org_files
+            .iter()
+            .filter(|f| {
+                f.front_matter.is_post()
+                    && f.front_matter.title.is_some()
+                    && !f.is_private(&self.user_config.site.ignored_directories, &self.dir_source)
+            })
+            .collect();
+        posts.sort_by(|a, b| {
+            b.front_matter
+                .date_created_ts
+                .cmp(&a.front_matter.date_created_ts)
+        });
+        posts
+    }
+
     /// collect_global_data loops through all org files and aggregates all
     /// links, logs, tags, into one place, then munges that data into
     /// more user friendly maps and  vectors for templates to consume.
@@ -307,6 +410,7 @@ impl<'a> Config<'a> {
             if !f.is_private(&self.user_config.site.ignored_directories, &self.dir_source) {
                 self.global_links.append(&mut f.links.clone());
                 self.global_logbook.append(&mut f.logbook.clone());
+                self.global_agenda.append(&mut f.agenda.clone());
                 self.global_tags.append(&mut f.tags.clone());
                 if f.front_matter.can_be_put_into_sitemap() {
                     self.global_sitemap.insert(
@@ -382,14 +486,59 @@ impl<'a> Config<'a> {
         }
         out.sort_by_key(|ld| ld.file.clone());
         self.sitemap = out;
+        let (tree, warnings) = sitemap_tree::build_tree(&self.sitemap);
+        self.sitemap_tree = tree;
+        self.sitemap_warnings = warnings;
+
+        // -- Posts --
+        self.posts = self
+            .public_posts()
+            .into_iter()
+            .map(|f| {
+                LinkData::new(
+                    f.full_url.clone(),
+                    f.front_matter.get_title().to_string(),
+                    LinkMeta::Post,
+                    Some(f.front_matter.clone()),
+                )
+            })
+            .collect();
+
+        // -- Logbook --
+        self.logbook = Arc::new(templates::Logbook::new(
+            &self.global_logbook,
+            &self.user_config.site.url,
+        ));
+
+        // -- Agenda --
+        self.agenda = agenda::collect(
+            &self.global_agenda,
+            &self.user_config.file.todo_keywords,
+            &self.user_config.site.url,
+        );
+
+        // -- Series --
+        self.series = series::collect(self);
+
+        // -- Archive --
+        self.archive = archive::summary(
+            &self.sitemap,
+            &self.user_config.site.url,
+            &self.user_config.archive.url,
+        );
+
+        // -- Graph --
+        self.graph = Graph::new(self);
+
+        self.site_context = templates::tera::site_context(self);
     }
 
     /// render - iterates over all org files and call their render function.
     fn render(&mut self, print_build_log: bool) {
-        let _failed_renders: Vec<_> = self
+        let mut _failed_renders: Vec<_> = self
             .org_files
             .par_iter()
-            .map(|f| f.render(self).map_err(|e| e))
+            .map(|f| f.render(self))
             .filter_map(|x| x.err())
             .collect();
         if print_build_log {
@@ -398,6 +547,8 @@ impl<'a> Config<'a> {
                 self.org_files.len() - _failed_renders.len()
             );
 
+            _failed_renders.extend(self.skipped_files.iter().cloned());
+            _failed_renders.extend(self.sitemap_warnings.iter().cloned());
             if !_failed_renders.is_empty() && print_build_log {
                 self.print_build_message(_failed_renders);
             }
@@ -411,7 +562,6 @@ impl<'a> Config<'a> {
         // let tera = templates::tera::load_templates(&self.dir_templates);
         fs::create_dir_all(&self.dir_tags).expect("Internal error: failed to create dir_tags.");
         for (tag_name, vec_of_tagged_items) in &self.tags_map {
-            let mut ctx = tera::Context::new();
             let mut template_tags: Vec<_> = vec_of_tagged_items
                 .iter()
                 .map(|i| data::Tag::new(i.to_owned(), self.user_config.site.url.to_string()))
@@ -425,22 +575,44 @@ impl<'a> Config<'a> {
                 template_tags.retain(|f| f.tag_type != "org")
             }
 
-            ctx.insert("tag_name", &tag_name);
-            ctx.insert("tagged_items", &template_tags);
-            ctx.insert("title", &tag_name);
-            ctx.insert("tags", &self.tags_list);
-            ctx.insert("sitemap", &self.sitemap);
-            ctx.insert("config", &self.user_config);
-
-            let tag_file_name = format!("{}.html", tag_name);
-            let out_path = self.dir_tags.join(tag_file_name);
-            let output = self.tera.render("[tag].html", &ctx).unwrap();
-            fs::write(&out_path, output).expect("failed to write tag file.")
+            let first_page = format!("{}{}.html", self.user_config.tags.url, tag_name);
+            let dir = format!("{}{}/", self.user_config.tags.url, tag_name);
+            let pages = pagination::paginate(
+                &template_tags,
+                self.user_config.tags.per_page,
+                &self.user_config.site.url,
+                &first_page,
+                &dir,
+            );
+            for page in pages {
+                let mut ctx = self.site_context.clone();
+                ctx.insert("tag_name", &tag_name);
+                ctx.insert("tagged_items", page.items);
+                ctx.insert("title", &tag_name);
+                page.insert(&mut ctx);
+
+                let out_path = self.dir_site_out.join(&page.web_path);
+                create_dir_all(out_path.parent().unwrap())
+                    .expect("Internal error: failed to create tag page dir.");
+                let output = self.tera.render("[tag].html", &ctx).unwrap();
+                fs::write(&out_path, output).expect("failed to write tag file.")
+            }
         }
     }
 
     // --TAGS: End--------------------------------------------------------------
 
+    /// data_api_build_index writes the sitemap as json, as the entry point
+    /// to the per-file json written by each OrgFile's render.
+    fn data_api_build_index(&self) -> anyhow::Result<()> {
+        if self.user_config.data_api.enabled {
+            let json = serde_json::to_string(&self.sitemap).context("Failed to serialize sitemap")?;
+            fs::write(self.dir_site_out.join(&self.user_config.data_api.index_path), json)
+                .context("Failed to write data api index")?;
+        }
+        Ok(())
+    }
+
     fn print_build_message(&self, failed_renders: Vec<FirnError>) {
         let mut report: HashMap<FirnErrorType, Vec<FirnError>> = HashMap::new();
         for err in failed_renders {
@@ -453,6 +625,33 @@ impl<'a> Config<'a> {
                 println!("{:?} private files were skipped.", priv_files.len());
             }
         }
+        if let Some(drafts) = report.get(&FirnErrorType::IsDraft) {
+            println!("{:?} drafts were skipped (build them with --drafts).", drafts.len());
+        }
+        if let Some(scheduled) = report.get(&FirnErrorType::IsScheduled) {
+            println!(
+                "{:?} files scheduled for later were skipped (build them with --future).",
+                scheduled.len()
+            );
+            if self.verbosity > 0 {
+                for err in scheduled {
+                    println!("  {}", err);
+                }
+            }
+        }
+
+        for kind in &[FirnErrorType::SitemapUnknownParent, FirnErrorType::SitemapCycle] {
+            for err in report.get(kind).into_iter().flatten() {
+                println!("⚠️ Warning: {}: {}", FirnError::get_err_name(kind.clone()), err);
+            }
+        }
+        for err in report
+            .get(&FirnErrorType::UnknownFootnotesMode)
+            .into_iter()
+            .flatten()
+        {
+            println!("Error: {}; the file was not rendered.", err);
+        }
     }
 
     pub fn setup_for_serve(&mut self, port: u16) {
@@ -496,13 +695,22 @@ impl<'a> Config<'a> {
 
     pub fn build(&mut self, print_build_log: bool) -> anyhow::Result<()> {
         if self.dir_firn.exists() {
-            self.load_firn_files();
-            self.parse_files();
-            self.collect_global_data();
+            self.load();
             self.cp_data();
             self.cp_static();
             self.tags_build_pages();
             self.render(print_build_log);
+            pagination::build_posts(self)?;
+            archive::build(self)?;
+            agenda::build(self)?;
+            templates::logbook::build(self)?;
+            feeds::build(self)?;
+            calendar::build(self)?;
+            crawlers::build(self)?;
+            search::build(self)?;
+            graph::build(self)?;
+            self.data_api_build_index()?;
+            gemini::build(self)?;
             if self.user_config.site.clean_attachments {
                 self.clean_up_attachments();
             }
@@ -514,10 +722,19 @@ impl<'a> Config<'a> {
         }
     }
 
+    /// load reads and parses every org file and collects their global data,
+    /// without writing anything to disk.
+    pub fn load(&mut self) {
+        self.load_firn_files();
+        self.parse_files();
+        self.collect_global_data();
+    }
+
     pub fn rebuild(&mut self, print_build_log: bool) -> anyhow::Result<()> {
         self.global_links.clear();
         self.global_tags.clear();
         self.global_logbook.clear();
+        self.global_agenda.clear();
         self.global_sitemap.clear();
         self.build(print_build_log)
     }
@@ -0,0 +1,128 @@
+use crate::{config::Config, org::OrgMetadata, templates::links::LinkData, util};
+
+use anyhow::{Context, Result};
+use orgize::export::HtmlEscape;
+use std::fs;
+
+// -- Crawlers -------------------------------------------------------------------
+//
+// sitemap.xml and robots.txt, built from the data that collect_global_data
+// has already munged for templates (the sitemap and tags lists).
+
+/// lastmod prefers the date a file was updated, then when it was created.
+fn lastmod(ld: &LinkData) -> Option<i64> {
+    ld.front_matter
+        .as_ref()
+        .and_then(|fm| fm.date_updated_ts.or(fm.date_created_ts))
+}
+
+/// A tag page was last modified whenever the most recent of its items was.
+fn tag_lastmod(tagged_items: &[OrgMetadata]) -> Option<i64> {
+    tagged_items
+        .iter()
+        .filter_map(|t| t.front_matter.date_updated_ts.or(t.front_matter.date_created_ts))
+        .max()
+}
+
+pub fn sitemap_xml(entries: &[(String, Option<i64>)]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (url, lastmod) in entries {
+        out.push_str("  <url>\n");
+        out.push_str(&format!("    <loc>{}</loc>\n", HtmlEscape(url)));
+        if let Some(ts) = lastmod {
+            out.push_str(&format!(
+                "    <lastmod>{}</lastmod>\n",
+                util::format_timestamp(*ts, "%Y-%m-%d")
+            ));
+        }
+        out.push_str("  </url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+pub fn robots_txt(disallow: &[String], sitemap_url: Option<String>) -> String {
+    let mut out = String::from("User-agent: *\n");
+    if disallow.is_empty() {
+        out.push_str("Disallow:\n");
+    }
+    for path in disallow {
+        out.push_str(&format!("Disallow: /{}\n", path.trim_start_matches('/')));
+    }
+    if let Some(sitemap_url) = sitemap_url {
+        out.push_str(&format!("\nSitemap: {}\n", sitemap_url));
+    }
+    out
+}
+
+/// build writes sitemap.xml and robots.txt to the _site folder.
+pub fn build(cfg: &Config) -> Result<()> {
+    let crawler_cfg = &cfg.user_config.crawlers;
+    let sitemap_url = format!("{}/sitemap.xml", cfg.user_config.site.url);
+
+    if crawler_cfg.sitemap {
+        // the sitemap is already filtered for firn_sitemap/firn_private/ignored dirs.
+        let mut entries: Vec<(String, Option<i64>)> = cfg
+            .sitemap
+            .iter()
+            .map(|ld| (ld.path.clone(), lastmod(ld)))
+            .collect();
+        for tag in &cfg.tags_list {
+            let tagged_items = cfg.tags_map.get(&tag.file).map(|v| &v[..]).unwrap_or(&[]);
+            entries.push((tag.path.clone(), tag_lastmod(tagged_items)));
+        }
+        fs::write(cfg.dir_site_out.join("sitemap.xml"), sitemap_xml(&entries))
+            .context("Failed to write sitemap.xml")?;
+    }
+
+    if crawler_cfg.robots {
+        let mut disallow = crawler_cfg.disallow.clone();
+        if crawler_cfg.disallow_ignored_directories {
+            let ignored_dirs = &cfg.user_config.site.ignored_directories;
+            disallow.extend(ignored_dirs.iter().map(|d| format!("{}/", d.trim_matches('/'))));
+        }
+        let sitemap_url = if crawler_cfg.sitemap {
+            Some(sitemap_url)
+        } else {
+            None
+        };
+        fs::write(
+            cfg.dir_site_out.join("robots.txt"),
+            robots_txt(&disallow, sitemap_url),
+        )
+        .context("Failed to write robots.txt")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap_xml() {
+        let xml = sitemap_xml(&[
+            ("https://mysite.com/a&b.html".to_string(), Some(1641081600)),
+            ("https://mysite.com/tags/rust.html".to_string(), None),
+        ]);
+        assert!(xml.contains("<loc>https://mysite.com/a&amp;b.html</loc>\n    <lastmod>2022-01-02</lastmod>"));
+        assert!(xml.contains("<loc>https://mysite.com/tags/rust.html</loc>\n  </url>"));
+    }
+
+    #[test]
+    fn test_robots_txt() {
+        assert_eq!(
+            "User-agent: *\nDisallow:\n",
+            robots_txt(&[], None)
+        );
+        assert_eq!(
+            "User-agent: *\nDisallow: /private/\nDisallow: /drafts/\n\nSitemap: https://mysite.com/sitemap.xml\n",
+            robots_txt(
+                &["private/".to_string(), "/drafts/".to_string()],
+                Some("https://mysite.com/sitemap.xml".to_string())
+            )
+        );
+    }
+}
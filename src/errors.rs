@@ -7,7 +7,12 @@ pub enum FirnErrorType {
     FrontMatterNoTitle,
     NoDefaultTemplateFound,
     IsPrivateFile,
-    HeadlineNotFound
+    IsDraft,
+    IsScheduled,
+    HeadlineNotFound,
+    SitemapUnknownParent,
+    SitemapCycle,
+    UnknownFootnotesMode,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -29,7 +34,12 @@ impl FirnError {
             FirnErrorType::FrontMatterNoTitle => "Missing `#+Title` frontmatter",
             FirnErrorType::NoDefaultTemplateFound => "No default.hbs template file found.", // it should just panic if this happens...
             FirnErrorType::IsPrivateFile => "File is private",
+            FirnErrorType::IsDraft => "File is a draft",
+            FirnErrorType::IsScheduled => "File is scheduled for later",
             FirnErrorType::HeadlineNotFound => "Headline not found",
+            FirnErrorType::SitemapUnknownParent => "`#+firn_under` parent not found",
+            FirnErrorType::SitemapCycle => "`#+firn_under` is circular",
+            FirnErrorType::UnknownFootnotesMode => "Unknown `#+firn_footnotes` mode",
         }
     }
 }
@@ -47,7 +57,12 @@ impl fmt::Display for FirnErrorType {
             FirnErrorType::FrontMatterNoTitle => write!(f, "No Title"),
             FirnErrorType::NoDefaultTemplateFound => write!(f, "No Template"),
             FirnErrorType::IsPrivateFile => write!(f, "File is private"),
+            FirnErrorType::IsDraft => write!(f, "File is a draft"),
+            FirnErrorType::IsScheduled => write!(f, "File is scheduled"),
             FirnErrorType::HeadlineNotFound => write!(f, "Headline not found."),
+            FirnErrorType::SitemapUnknownParent => write!(f, "Unknown parent"),
+            FirnErrorType::SitemapCycle => write!(f, "Circular parent"),
+            FirnErrorType::UnknownFootnotesMode => write!(f, "Unknown footnotes mode"),
         }
     }
 }
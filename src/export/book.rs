@@ -0,0 +1,148 @@
+use super::{file_headlines, link_files, toc_list};
+use crate::{
+    config::Config,
+    footnotes,
+    org::OrgFile,
+    templates::Render,
+    util::{self, headline_href},
+};
+
+use anyhow::{Context, Result};
+use orgize::export::HtmlEscape;
+use std::fs;
+use std::path::Path;
+
+// -- Book -----------------------------------------------------------------------
+//
+// A book is every selected file on one html page: each file is a section
+// with its own anchor, links between the files jump within the page, and a
+// table of contents covers every file and headline.
+
+/// file_anchor is the id of a file's section, from its path (ex: blog/post.org -> blog-post).
+fn file_anchor(cfg: &Config, file: &OrgFile) -> String {
+    let path = file
+        .file_path
+        .strip_prefix(&cfg.dir_source)
+        .unwrap_or(&file.file_path)
+        .with_extension("");
+    util::slugify(&util::path_to_string(&path))
+}
+
+/// prefix_headline_ids keeps headline and footnote ids unique across files,
+/// as every file can have its own "Notes" headline and its own `fn-1`. Links
+/// to a headline in the same file (`[[*Notes]]`) are prefixed along with it.
+fn prefix_headline_ids(html: &str, anchor: &str, headlines: &[(usize, String)]) -> String {
+    let mut html = html.to_string();
+    for (_, raw) in headlines {
+        html = html.replace(
+            &format!(" id=\"{}\">", raw),
+            &format!(" id=\"{}-{}\">", anchor, raw),
+        );
+        html = html.replace(
+            &format!("href=\"{}\"", headline_href("", raw)),
+            &format!(
+                "href=\"{}\"",
+                headline_href("", &format!("{}-{}", anchor, raw))
+            ),
+        );
+    }
+    for kind in footnotes::ID_KINDS {
+        for attr in [" id=\"", "href=\"#", "for=\""] {
+            html = html.replace(
+                &format!("{}{}-", attr, kind),
+                &format!("{}{}-{}-", attr, anchor, kind),
+            );
+        }
+    }
+    html
+}
+
+/// export renders `files` into one html page at `out`.
+pub fn export(cfg: &Config, files: &[&OrgFile], title: &str, out: &Path) -> Result<()> {
+    let anchors: Vec<String> = files.iter().map(|f| file_anchor(cfg, f)).collect();
+    let targets: Vec<String> = anchors.iter().map(|a| format!("#{}", a)).collect();
+    let mut toc_items = Vec::new();
+    let mut content = String::new();
+
+    for ((file, anchor), target) in files.iter().zip(&anchors).zip(&targets) {
+        let file_title = file.front_matter.get_title();
+        let headlines = file_headlines(file);
+        // shift headlines down a level, as the file's title is the h1.
+        let body = Render::new(file, cfg).render_entire_file(Some(1));
+        let body = prefix_headline_ids(&link_files(&body, files, &targets), anchor, &headlines);
+
+        toc_items.push((0, file_title.to_string(), target.clone()));
+        for (level, raw) in headlines {
+            let href = headline_href("", &format!("{}-{}", anchor, raw));
+            toc_items.push((level, raw, href));
+        }
+        content.push_str(&format!(
+            "<section class=\"firn-book-file\" id=\"{}\"><h1>{}</h1>{}</section>\n",
+            anchor,
+            HtmlEscape(file_title),
+            body
+        ));
+    }
+    let toc = toc_list(&toc_items);
+
+    let layout = format!("{}.html", cfg.user_config.book.layout);
+    let output = if cfg.tera.get_template_names().any(|t| t == layout) {
+        let mut ctx = tera::Context::new();
+        ctx.insert("title", title);
+        ctx.insert("toc", &toc);
+        ctx.insert("content", &content);
+        ctx.insert("sitemap", &cfg.sitemap);
+        ctx.insert("tags", &cfg.tags_list);
+        ctx.insert("config", &cfg.user_config);
+        cfg.tera
+            .render(&layout, &ctx)
+            .context("Failed to render book layout")?
+    } else {
+        format!(
+            "<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n<nav class=\"firn-book-toc\">{1}</nav>\n{2}</body>\n</html>\n",
+            HtmlEscape(title),
+            toc,
+            content
+        )
+    };
+
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent).context("Failed to create export directory")?;
+    }
+    fs::write(out, output).context("Failed to write book")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_headline_ids() {
+        let headlines = vec![(1, "Notes".to_string()), (2, "Read more".to_string())];
+        assert_eq!(
+            "<h2 id=\"blog-post-Notes\">Notes</h2><h3 class=\"firn-TODO\" id=\"blog-post-Read more\">Read more</h3>",
+            prefix_headline_ids(
+                "<h2 id=\"Notes\">Notes</h2><h3 class=\"firn-TODO\" id=\"Read more\">Read more</h3>",
+                "blog-post",
+                &headlines
+            )
+        );
+        assert_eq!(
+            "<p>See <a href=\"#blog-post-Read%20more\">below</a>.</p>",
+            prefix_headline_ids(
+                "<p>See <a href=\"#Read%20more\">below</a>.</p>",
+                "blog-post",
+                &headlines
+            )
+        );
+        assert_eq!(
+            "<sup><a id=\"blog-post-fnref-1\" href=\"#blog-post-fn-1\">1</a></sup><label for=\"blog-post-sn-2\"></label>",
+            prefix_headline_ids(
+                "<sup><a id=\"fnref-1\" href=\"#fn-1\">1</a></sup><label for=\"sn-2\"></label>",
+                "blog-post",
+                &[]
+            )
+        );
+    }
+}
@@ -0,0 +1,423 @@
+use super::{file_headlines, link_files, toc_list};
+use crate::{
+    config::Config,
+    org::OrgFile,
+    templates::Render,
+    util::{self, headline_href},
+};
+
+use anyhow::{Context, Result};
+use orgize::export::HtmlEscape;
+use slugify::slugify;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+// -- EPUB -----------------------------------------------------------------------
+//
+// An EPUB 3 is a zip file with a fixed layout: an uncompressed `mimetype`
+// entry first, a container pointing to the package (content.opf), which lists
+// every chapter and image, and a nav document for the table of contents.
+
+pub struct Chapter {
+    pub title: String,
+    pub file_name: String,
+    pub body: String,
+    /// (level, raw title) of every headline, for the table of contents; their
+    /// ids in `body` are slugs of the title (see slug_headline_ids).
+    pub headlines: Vec<(usize, String)>,
+}
+
+pub struct Resource {
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Epub {
+    pub title: String,
+    pub identifier: String,
+    pub language: String,
+    pub chapters: Vec<Chapter>,
+    pub resources: Vec<Resource>,
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn media_type(file_name: &str) -> &'static str {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match &extension[..] {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Epub {
+    fn content_opf(&self, modified: &str) -> String {
+        let mut manifest = String::from(
+            "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        );
+        let mut spine = String::new();
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                i + 1,
+                chapter.file_name
+            ));
+            spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", i + 1));
+        }
+        for (i, resource) in self.resources.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"resource-{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                i + 1,
+                HtmlEscape(&resource.file_name),
+                media_type(&resource.file_name)
+            ));
+        }
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:language>{}</dc:language>
+    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+{}  </manifest>
+  <spine>
+{}  </spine>
+</package>
+"#,
+            HtmlEscape(&self.identifier),
+            HtmlEscape(&self.title),
+            HtmlEscape(&self.language),
+            modified,
+            manifest,
+            spine
+        )
+    }
+
+    fn nav_xhtml(&self) -> String {
+        let mut items = Vec::new();
+        for chapter in &self.chapters {
+            items.push((0, chapter.title.clone(), chapter.file_name.clone()));
+            for (level, raw) in &chapter.headlines {
+                let href = headline_href(&chapter.file_name, &headline_id(raw));
+                items.push((*level, raw.clone(), href));
+            }
+        }
+        xhtml_document(
+            "Table of Contents",
+            &format!(
+                "<nav epub:type=\"toc\" id=\"toc\"><h1>Table of Contents</h1>{}</nav>",
+                toc_list(&items)
+            ),
+        )
+    }
+
+    /// write zips the book into `w`.
+    pub fn write<W: Write + Seek>(&self, w: W) -> zip::result::ZipResult<()> {
+        let mut zip = ZipWriter::new(w);
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+
+        // the mimetype must come first, uncompressed.
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(self.content_opf(&modified).as_bytes())?;
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(self.nav_xhtml().as_bytes())?;
+        for chapter in &self.chapters {
+            zip.start_file(format!("OEBPS/{}", chapter.file_name), deflated)?;
+            let body = format!("<h1>{}</h1>{}", HtmlEscape(&chapter.title), chapter.body);
+            zip.write_all(xhtml_document(&chapter.title, &body).as_bytes())?;
+        }
+        for resource in &self.resources {
+            zip.start_file(format!("OEBPS/{}", resource.file_name), stored)?;
+            zip.write_all(&resource.bytes)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+fn xhtml_document(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{}</title></head>
+<body>{}</body>
+</html>
+"#,
+        HtmlEscape(title),
+        body
+    )
+}
+
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// html_to_xhtml closes the void elements (ex: `<img src="a.png">`), which
+/// xhtml requires.
+fn html_to_xhtml(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let name_len = rest[1..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len() - 1);
+        let name = rest[1..=name_len].to_ascii_lowercase();
+        // the tag ends at the first `>` outside of a quoted attribute.
+        let mut quote = None;
+        let end = rest.char_indices().find(|&(_, c)| match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                false
+            }
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                false
+            }
+            None => c == '>',
+        });
+        let end = match end {
+            Some((end, _)) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        out.push_str(tag);
+        if VOID_ELEMENTS.contains(&&name[..]) && !tag.ends_with('/') {
+            out.push('/');
+        }
+        out.push('>');
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// headline_id is a headline's id in a chapter; orgize's ids are the raw
+/// title, which can hold characters (ex: `&`, `<`) that break xhtml.
+fn headline_id(raw: &str) -> String {
+    slugify!(raw)
+}
+
+/// slug_headline_ids replaces the raw headline ids in a chapter's body, and
+/// the links to them from the same chapter, with headline_id.
+fn slug_headline_ids(html: &str, headlines: &[(usize, String)]) -> String {
+    let mut html = html.to_string();
+    for (_, raw) in headlines {
+        html = html.replace(
+            &format!(" id=\"{}\">", raw),
+            &format!(" id=\"{}\">", headline_id(raw)),
+        );
+        html = html.replace(
+            &format!("href=\"{}\"", HtmlEscape(headline_href("", raw))),
+            &format!("href=\"#{}\"", headline_id(raw)),
+        );
+    }
+    html
+}
+
+/// export renders `files` and writes them, with their images, as an epub to `out`.
+pub fn export(cfg: &Config, files: &[&OrgFile], title: &str, out: &Path) -> Result<()> {
+    let chapter_names: Vec<String> = (1..=files.len())
+        .map(|i| format!("chapter-{}.xhtml", i))
+        .collect();
+    let mut resources: Vec<Resource> = Vec::new();
+    let mut resource_names: HashMap<PathBuf, String> = HashMap::new();
+    let mut chapters: Vec<Chapter> = Vec::new();
+
+    for (file, file_name) in files.iter().zip(&chapter_names) {
+        // shift headlines down a level, as the chapter title is the h1.
+        let headlines = file_headlines(file);
+        let body = Render::new(file, cfg).render_entire_file(Some(1));
+        let mut body = html_to_xhtml(&slug_headline_ids(&body, &headlines));
+
+        // links to other files in the book point to their chapter instead.
+        body = link_files(&body, files, &chapter_names);
+
+        // images are copied into the book, and their urls replaced.
+        for attachment in &file.attachments {
+            if !util::org_str_is_img_link(attachment) {
+                continue;
+            }
+            let url = util::transform_org_link_to_html(
+                cfg.base_url.clone(),
+                attachment.clone(),
+                file.file_path.clone(),
+            );
+            let src_path = file
+                .file_path
+                .parent()
+                .expect("File had no parent")
+                .join(attachment.trim_start_matches("file:"));
+            if !resource_names.contains_key(&src_path) {
+                let bytes = match fs::read(&src_path) {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        println!("⚠️ Warning: could not read image {:?}", src_path);
+                        continue;
+                    }
+                };
+                let name = format!(
+                    "images/{}-{}",
+                    resources.len() + 1,
+                    src_path.file_name().unwrap().to_string_lossy()
+                );
+                resources.push(Resource {
+                    file_name: name.clone(),
+                    bytes,
+                });
+                resource_names.insert(src_path.clone(), name);
+            }
+            body = body.replace(
+                &format!("src=\"{}\"", url),
+                &format!("src=\"{}\"", resource_names[&src_path]),
+            );
+        }
+
+        chapters.push(Chapter {
+            title: file.front_matter.get_title().to_string(),
+            file_name: file_name.clone(),
+            body,
+            headlines,
+        });
+    }
+
+    let epub = Epub {
+        title: title.to_string(),
+        identifier: format!("{}#{}", cfg.user_config.site.url, util::slugify(title)),
+        language: cfg.user_config.book.language.clone(),
+        chapters,
+        resources,
+    };
+
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent).context("Failed to create export directory")?;
+    }
+    let out_file = fs::File::create(out).context("Failed to create epub file")?;
+    epub.write(out_file).context("Failed to write epub")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_write_epub() {
+        let epub = Epub {
+            title: "My Book".to_string(),
+            identifier: "https://mysite.com#my-book".to_string(),
+            language: "en".to_string(),
+            chapters: vec![Chapter {
+                title: "First".to_string(),
+                file_name: "chapter-1.xhtml".to_string(),
+                body: "<h2 id=\"intro\">Intro</h2><img src=\"images/1-cat.png\"/>".to_string(),
+                headlines: vec![(1, "Intro".to_string())],
+            }],
+            resources: vec![Resource {
+                file_name: "images/1-cat.png".to_string(),
+                bytes: vec![1, 2, 3],
+            }],
+        };
+        let mut buf = Cursor::new(Vec::new());
+        epub.write(&mut buf).unwrap();
+
+        let mut archive = zip::ZipArchive::new(buf).unwrap();
+        let mut read = |name: &str| {
+            let mut s = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut s)
+                .unwrap();
+            s
+        };
+        assert_eq!("application/epub+zip", read("mimetype"));
+        assert!(read("OEBPS/content.opf").contains(
+            "<item id=\"resource-1\" href=\"images/1-cat.png\" media-type=\"image/png\"/>"
+        ));
+        assert!(read("OEBPS/nav.xhtml").contains("<a href=\"chapter-1.xhtml#intro\">Intro</a>"));
+        assert!(read("OEBPS/chapter-1.xhtml").contains("<h1>First</h1><h2 id=\"intro\">"));
+
+        let first = archive.by_index(0).unwrap();
+        assert_eq!("mimetype", first.name());
+        assert_eq!(CompressionMethod::Stored, first.compression());
+    }
+
+    #[test]
+    fn test_headline_ids() {
+        let headlines = vec![(1, "Q&A <stuff>".to_string())];
+        assert_eq!(
+            "<p><a href=\"#q-a-stuff\">see</a></p>",
+            slug_headline_ids(
+                "<p><a href=\"#Q&amp;A%20&lt;stuff&gt;\">see</a></p>",
+                &headlines
+            )
+        );
+        let body = slug_headline_ids(
+            "<h2 id=\"Q&A <stuff>\">Q&amp;A &lt;stuff&gt;</h2>",
+            &headlines,
+        );
+        assert_eq!("<h2 id=\"q-a-stuff\">Q&amp;A &lt;stuff&gt;</h2>", body);
+
+        let epub = Epub {
+            title: "My Book".to_string(),
+            identifier: "https://mysite.com#my-book".to_string(),
+            language: "fr".to_string(),
+            chapters: vec![Chapter {
+                title: "First".to_string(),
+                file_name: "chapter-1.xhtml".to_string(),
+                body,
+                headlines,
+            }],
+            resources: vec![],
+        };
+        assert!(epub
+            .nav_xhtml()
+            .contains("<a href=\"chapter-1.xhtml#q-a-stuff\">Q&amp;A &lt;stuff&gt;</a>"));
+        assert!(epub
+            .content_opf("2022-01-01T00:00:00Z")
+            .contains("<dc:language>fr</dc:language>"));
+    }
+
+    #[test]
+    fn test_html_to_xhtml() {
+        assert_eq!(
+            "<p>a<br/>b</p><hr/><img src=\"a>b.png\" alt=\"cat\"/><input type=\"checkbox\"/><img src=\"c.png\"/>",
+            html_to_xhtml(
+                "<p>a<br>b</p><hr><img src=\"a>b.png\" alt=\"cat\"><input type=\"checkbox\"><img src=\"c.png\"/>"
+            )
+        );
+    }
+}
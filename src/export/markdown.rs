@@ -0,0 +1,399 @@
+use crate::{config::Config, footnotes, front_matter::FrontMatter, org::OrgFile, util};
+
+use anyhow::{Context, Result};
+use orgize::elements::TableRow;
+use orgize::{Element, Event, Org};
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// -- Markdown -------------------------------------------------------------------
+//
+// Writes every public file as CommonMark (with GFM tables and strikethrough),
+// with its front matter as yaml, for cross-posting. The files keep their
+// place in the site, so relative links between them, and to the attachments
+// copied alongside them, keep working.
+
+#[derive(Default)]
+struct MarkdownWriter {
+    /// quote blocks write into their own buffer, which is `> ` prefixed when they end.
+    buffers: Vec<String>,
+    list_indents: Vec<bool>,
+    table_rows: usize,
+    table_cells: usize,
+    /// the footnotes, written after the file.
+    footnotes: footnotes::Labelled,
+}
+
+impl MarkdownWriter {
+    fn out(&mut self) -> &mut String {
+        self.buffers.last_mut().expect("buffers is never empty")
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out().push_str(s)
+    }
+
+    fn fenced(&mut self, language: &str, contents: &str) {
+        self.push(&format!("```{}\n{}", language, contents));
+        if !contents.ends_with('\n') {
+            self.push("\n");
+        }
+        self.push("```\n\n");
+    }
+
+    fn start(&mut self, element: &Element, resolve_link: &dyn Fn(&str) -> String) {
+        match element {
+            Element::Title(title) => {
+                self.push(&format!("{} ", "#".repeat(title.level.min(6))));
+                if let Some(keyword) = &title.keyword {
+                    self.push(&format!("{} ", keyword));
+                }
+            }
+            Element::List(list) => self.list_indents.push(list.ordered),
+            Element::ListItem(_) => {
+                let depth = self.list_indents.len().saturating_sub(1);
+                let bullet = if *self.list_indents.last().unwrap_or(&false) {
+                    "1. "
+                } else {
+                    "- "
+                };
+                self.push(&format!("{}{}", "  ".repeat(depth), bullet));
+            }
+            Element::QuoteBlock(_) => self.buffers.push(String::new()),
+            Element::Table(_) => self.table_rows = 0,
+            Element::TableRow(TableRow::Header) | Element::TableRow(TableRow::Body) => {
+                self.table_cells = 0;
+                self.push("|");
+            }
+            Element::TableCell(_) => {
+                self.table_cells += 1;
+                self.push(" ");
+            }
+            Element::SourceBlock(block) => self.fenced(&block.language, &block.contents),
+            Element::ExampleBlock(block) => self.fenced("", &block.contents),
+            Element::FixedWidth(fixed) => self.fenced("", &fixed.value),
+            Element::Bold => self.push("**"),
+            Element::Italic => self.push("*"),
+            Element::Strike => self.push("~~"),
+            Element::Text { value } => {
+                let line_start = self.out().is_empty() || self.out().ends_with('\n');
+                self.push(&escape(value, line_start))
+            }
+            Element::Code { value } | Element::Verbatim { value } => {
+                self.push(&format!("`{}`", value))
+            }
+            Element::InlineSrc(src) => self.push(&format!("`{}`", src.body)),
+            Element::FnRef(fn_ref) => {
+                let label = self.footnotes.reference(fn_ref);
+                self.push(&format!("[^{}]", label))
+            }
+            Element::FnDef(fn_def) => self.push(&format!("[^{}]: ", fn_def.label)),
+            Element::Rule(_) => self.push("---\n\n"),
+            Element::Link(link) => {
+                let url = resolve_link(&link.path);
+                if util::org_str_is_img_link(&link.path) {
+                    let alt = link.desc.as_deref().unwrap_or("");
+                    self.push(&format!("![{}]({})", escape(alt, false), url));
+                } else {
+                    let desc = link.desc.as_ref().unwrap_or(&link.path);
+                    self.push(&format!("[{}]({})", escape(desc, false), url));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, element: &Element) {
+        match element {
+            Element::Title(_) => self.push("\n\n"),
+            Element::Bold => self.push("**"),
+            Element::Italic => self.push("*"),
+            Element::Strike => self.push("~~"),
+            // paragraphs in lists are the item's text, so only end the line.
+            Element::Paragraph { .. } if !self.list_indents.is_empty() => {
+                let out = self.out();
+                let trimmed = out.trim_end().len();
+                out.truncate(trimmed);
+                out.push('\n');
+            }
+            Element::Paragraph { .. } => {
+                let out = self.out();
+                let trimmed = out.trim_end().len();
+                out.truncate(trimmed);
+                out.push_str("\n\n");
+            }
+            Element::List(_) => {
+                self.list_indents.pop();
+                if self.list_indents.is_empty() {
+                    self.push("\n");
+                }
+            }
+            Element::QuoteBlock(_) => {
+                let quote = self.buffers.pop().unwrap_or_default();
+                let quoted: Vec<String> = quote
+                    .trim_end()
+                    .lines()
+                    .map(|l| {
+                        if l.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", l)
+                        }
+                    })
+                    .collect();
+                self.push(&format!("{}\n\n", quoted.join("\n")));
+            }
+            Element::TableCell(_) => self.push(" |"),
+            Element::TableRow(TableRow::Header) | Element::TableRow(TableRow::Body) => {
+                self.push("\n");
+                self.table_rows += 1;
+                // markdown tables always have a header, so the first row is it.
+                if self.table_rows == 1 {
+                    let rule = format!("|{}\n", " --- |".repeat(self.table_cells));
+                    self.push(&rule);
+                }
+            }
+            Element::Table(_) => self.push("\n"),
+            _ => {}
+        }
+    }
+}
+
+/// escape backslash-escapes the characters markdown would read as formatting
+/// in org's plain text. `#` is only escaped at the start of a line, where it
+/// would start a heading.
+fn escape(text: &str, line_start: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut at_line_start = line_start;
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' => out.push('\\'),
+            '#' if at_line_start => out.push('\\'),
+            _ => {}
+        }
+        out.push(c);
+        at_line_start = c == '\n' || (at_line_start && c == ' ');
+    }
+    out
+}
+
+/// to_markdown walks the parsed org content, the way MyHtmlHandler does for
+/// html, and writes it out as markdown, followed by its footnotes (the file's
+/// `definitions`, see footnotes.rs, and inline ones). Links are resolved with
+/// `resolve_link`.
+pub fn to_markdown(
+    parsed: &Org,
+    definitions: Vec<(String, String)>,
+    resolve_link: &dyn Fn(&str) -> String,
+) -> String {
+    let mut writer = MarkdownWriter {
+        buffers: vec![String::new()],
+        footnotes: footnotes::Labelled::new(definitions),
+        ..MarkdownWriter::default()
+    };
+    for event in parsed.iter() {
+        match event {
+            Event::Start(element) => writer.start(element, resolve_link),
+            Event::End(element) => writer.end(element),
+        }
+    }
+    let mut out = writer.buffers.concat().trim().to_string() + "\n";
+    if !writer.footnotes.notes.is_empty() {
+        out.push('\n');
+    }
+    for (label, text) in &writer.footnotes.notes {
+        out.push_str(&footnote_definition(label, text, resolve_link));
+    }
+    out
+}
+
+/// footnote_definition writes a footnote's org text as a markdown footnote,
+/// indenting the lines after the first so they stay part of it.
+fn footnote_definition(label: &str, text: &str, resolve_link: &dyn Fn(&str) -> String) -> String {
+    let markdown = to_markdown(&Org::parse(text), vec![], resolve_link);
+    let lines: Vec<String> = markdown
+        .trim()
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => format!("[^{}]: {}", label, line),
+            _ if line.is_empty() => String::new(),
+            _ => format!("    {}", line),
+        })
+        .collect();
+    lines.join("\n") + "\n"
+}
+
+/// MarkdownFrontMatter is the part of a file's front matter that other static
+/// site generators read, with the names they use for it.
+#[derive(Serialize)]
+struct MarkdownFrontMatter<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<&'a String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layout: Option<&'a String>,
+}
+
+/// front_matter_yaml writes a file's title, dates, tags and layout, where
+/// they are set, as a yaml block.
+pub fn front_matter_yaml(front_matter: &FrontMatter) -> Result<String> {
+    let markdown_front_matter = MarkdownFrontMatter {
+        title: front_matter.title.as_ref(),
+        date: front_matter.date_created.as_ref(),
+        updated: front_matter.date_updated.as_ref(),
+        tags: front_matter.firn_tags.as_ref(),
+        layout: front_matter.layout.as_ref(),
+    };
+    let yaml = serde_yaml::to_string(&markdown_front_matter)
+        .context("Failed to serialize front matter")?;
+    Ok(format!("{}\n---\n", yaml.trim_end()))
+}
+
+/// markdown_link keeps links relative, as the files keep their place in the
+/// site: `file:` links to org files point to their .md file instead.
+fn markdown_link(link: &str) -> String {
+    if util::is_local_org_file(link) {
+        let path = link.trim_start_matches("file:");
+        format!("{}.md", path.strip_suffix(".org").unwrap_or(path))
+    } else {
+        link.trim_start_matches("file:").to_string()
+    }
+}
+
+/// normalize resolves `..` and `.` in a path without touching the disk.
+fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            _ => out.push(comp),
+        }
+    }
+    out
+}
+
+/// copy_attachments copies a file's local attachments to the same place
+/// relative to its markdown file.
+fn copy_attachments(cfg: &Config, file: &OrgFile, dir_out: &Path) -> Result<()> {
+    let file_dir = file.file_path.parent().expect("File had no parent");
+    for attachment in &file.attachments {
+        let src = normalize(&file_dir.join(attachment.trim_start_matches("file:")));
+        // attachments outside of the site aren't copied.
+        let rel = match src.strip_prefix(&cfg.dir_source) {
+            Ok(rel) => rel,
+            Err(_) => continue,
+        };
+        if !src.is_file() {
+            println!("⚠️ Warning: could not find attachment {:?}", src);
+            continue;
+        }
+        let dest = dir_out.join(rel);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).context("Failed to create attachment directory")?;
+        }
+        fs::copy(&src, &dest).context("Failed to copy attachment")?;
+    }
+    Ok(())
+}
+
+/// export writes every public file, and its attachments, as markdown into `dir_out`.
+pub fn export(cfg: &Config, dir_out: &Path) -> Result<usize> {
+    let mut count = 0;
+    for file in &cfg.org_files {
+        if file.front_matter.title.is_none()
+            || file.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+        {
+            continue;
+        }
+        let markdown = format!(
+            "{}\n{}",
+            front_matter_yaml(&file.front_matter)?,
+            to_markdown(
+                &file.parsed,
+                footnotes::split_definitions(&file.original_org).1,
+                &markdown_link
+            )
+        );
+
+        let out_path = dir_out.join(file.web_path.with_extension("md"));
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create markdown directory")?;
+        }
+        fs::write(&out_path, markdown).context("Failed to write markdown file")?;
+        copy_attachments(cfg, file, dir_out)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let parsed = Org::parse(
+            "#+title: Test\n\nSome *bold* and =code= with a [[file:other.org][link]].\n\n* TODO First\n- one\n  - nested\n- two\n\n** Second\n#+begin_src rust\nfn main() {}\n#+end_src\n\n| a | b |\n|---+---|\n| 1 | 2 |\n\n#+begin_quote\nA quote.\n#+end_quote\n\n[[file:cat.png]]\n",
+        );
+        assert_eq!(
+            "Some **bold** and `code` with a [link](other.md).\n\n# TODO First\n\n- one\n  - nested\n- two\n\n## Second\n\n```rust\nfn main() {}\n```\n\n| a | b |\n| --- | --- |\n| 1 | 2 |\n\n> A quote.\n\n![](cat.png)\n",
+            to_markdown(&parsed, vec![], &markdown_link)
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let parsed = Org::parse("#1 pick: snake_case, 2*3 and [not a link].\n");
+        assert_eq!(
+            "\\#1 pick: snake\\_case, 2\\*3 and \\[not a link\\].\n",
+            to_markdown(&parsed, vec![], &markdown_link)
+        );
+    }
+
+    #[test]
+    fn test_markdown_link() {
+        assert_eq!(
+            "notes.org-old/index.md",
+            markdown_link("file:notes.org-old/index.org")
+        );
+        assert_eq!("cat.png", markdown_link("file:cat.png"));
+        assert_eq!("https://example.com", markdown_link("https://example.com"));
+    }
+
+    #[test]
+    fn test_front_matter_yaml() {
+        let front_matter = FrontMatter {
+            title: Some("Test".to_string()),
+            date_created: Some("2022-01-02".to_string()),
+            date_created_ts: Some(1641081600),
+            firn_tags: Some(vec!["rust".to_string()]),
+            firn_search: true,
+            ..FrontMatter::default()
+        };
+        assert_eq!(
+            "---\ntitle: Test\ndate: 2022-01-02\ntags:\n  - rust\n---\n",
+            front_matter_yaml(&front_matter).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let (org, definitions) = footnotes::split_definitions(
+            "Defined[fn:1], anonymous[fn::An *inline* note.] and named[fn:aside:On the side.], twice[fn:aside].\n\n[fn:1] A [[https://example.com][linked]] note,\nover two lines.\n",
+        );
+        assert_eq!(
+            "Defined[^1], anonymous[^anonymous-1] and named[^aside], twice[^aside].\n\n[^1]: A [linked](https://example.com) note,\n    over two lines.\n[^anonymous-1]: An **inline** note.\n[^aside]: On the side.\n",
+            to_markdown(&Org::parse(&org), definitions, &markdown_link)
+        );
+    }
+}
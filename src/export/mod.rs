@@ -0,0 +1,231 @@
+pub mod book;
+pub mod epub;
+pub mod markdown;
+
+use crate::{config::Config, org::OrgFile, query, util};
+
+use clap::{Args, Subcommand};
+use orgize::export::HtmlEscape;
+use std::path::PathBuf;
+
+// -- Export ---------------------------------------------------------------------
+//
+// Exports turn a loaded site (see Config::load) into formats other than the
+// html site that `firn build` writes.
+
+#[derive(Subcommand)]
+pub enum Export {
+    /// Package files into an EPUB 3 e-book
+    Epub(Selection),
+    /// Stitch files into a single html page, for printing
+    Book(Selection),
+    /// Write every public file as markdown, with its attachments
+    Markdown {
+        /// Directory to write to, defaults to _firn/_exports/markdown
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+pub struct Selection {
+    /// Only include files with `#+firn_under` set to this value
+    #[clap(long)]
+    under: Option<String>,
+
+    /// Include only these files (by title or path), in this order
+    #[clap(long = "file")]
+    files: Vec<String>,
+
+    /// Title of the export, defaults to the site title
+    #[clap(long)]
+    title: Option<String>,
+
+    /// Where to write the export, defaults to _firn/_exports/<title>.<format>
+    #[clap(short, long)]
+    out: Option<PathBuf>,
+}
+
+impl Selection {
+    fn title(&self, cfg: &Config) -> String {
+        self.title
+            .clone()
+            .unwrap_or_else(|| cfg.user_config.site.title.clone())
+    }
+
+    fn out(&self, cfg: &Config, extension: &str) -> PathBuf {
+        self.out
+            .clone()
+            .unwrap_or_else(|| default_out_path(cfg, &self.title(cfg), extension))
+    }
+}
+
+/// select_files picks the files to export: either the `files` asked for, in
+/// that order, or every file in the sitemap (optionally only those
+/// `#+firn_under` a value), by `#+firn_order` and then oldest first.
+pub fn select_files<'c, 'a>(
+    cfg: &'c Config<'a>,
+    under: Option<&str>,
+    files: &[String],
+) -> Vec<&'c OrgFile<'a>> {
+    if !files.is_empty() {
+        return files
+            .iter()
+            .map(|needle| match query::find_file(cfg, needle) {
+                Some(f) => f,
+                None => {
+                    println!(
+                        "Error: no public file found with the title or path {:?}",
+                        needle
+                    );
+                    util::exit();
+                }
+            })
+            .collect();
+    }
+
+    let mut selected: Vec<_> = cfg
+        .org_files
+        .iter()
+        .filter(|f| {
+            f.front_matter.can_be_put_into_sitemap()
+                && !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+        })
+        .filter(|f| match under {
+            Some(under) => f
+                .front_matter
+                .firn_under
+                .as_ref()
+                .map(|parents| parents.iter().any(|p| p.eq_ignore_ascii_case(under)))
+                .unwrap_or(false),
+            None => true,
+        })
+        .collect();
+    // files without a firn_order, and then without a date_created, go last.
+    selected.sort_by_key(|f| {
+        (
+            f.front_matter.firn_order.is_none(),
+            f.front_matter.firn_order,
+            f.front_matter.date_created_ts.is_none(),
+            f.front_matter.date_created_ts,
+            f.front_matter.get_title().to_string(),
+        )
+    });
+    selected
+}
+
+/// default_out_path is where an export is written when no path is given.
+pub fn default_out_path(cfg: &Config, name: &str, extension: &str) -> PathBuf {
+    cfg.dir_firn
+        .join("_exports")
+        .join(util::slugify(name))
+        .with_extension(extension)
+}
+
+/// file_headlines lists the (level, raw title) of every headline in a file.
+pub fn file_headlines(file: &OrgFile) -> Vec<(usize, String)> {
+    file.parsed
+        .headlines()
+        .map(|hl| {
+            let hl_title = hl.title(&file.parsed);
+            (hl_title.level, hl_title.raw.to_string())
+        })
+        .collect()
+}
+
+/// link_files points links to any of `files` (by their full_url) at the
+/// matching entry of `targets` instead.
+pub fn link_files(html: &str, files: &[&OrgFile], targets: &[String]) -> String {
+    let mut html = html.to_string();
+    for (file, target) in files.iter().zip(targets) {
+        html = html.replace(
+            &format!("href=\"{}\"", file.full_url),
+            &format!("href=\"{}\"", target),
+        );
+    }
+    html
+}
+
+/// toc_list writes nested `<ol>`s for (level, label, href) entries.
+/// A jump of more than one level is treated as a single level deeper.
+pub fn toc_list(items: &[(usize, String, String)]) -> String {
+    let mut out = String::from("<ol>");
+    let mut levels: Vec<usize> = Vec::new();
+    for (level, label, href) in items {
+        match levels.last() {
+            None => levels.push(*level),
+            Some(last) if level > last => {
+                out.push_str("<ol>");
+                levels.push(*level);
+            }
+            Some(_) => {
+                out.push_str("</li>");
+                while levels.len() > 1 && level < levels.last().unwrap() {
+                    out.push_str("</ol></li>");
+                    levels.pop();
+                }
+            }
+        }
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            HtmlEscape(href),
+            HtmlEscape(label)
+        ));
+    }
+    if !levels.is_empty() {
+        out.push_str("</li>");
+    }
+    while levels.len() > 1 {
+        out.push_str("</ol></li>");
+        levels.pop();
+    }
+    out.push_str("</ol>");
+    out
+}
+
+pub fn run(cfg: &Config, export: &Export) -> anyhow::Result<()> {
+    let (count, out) = match export {
+        Export::Epub(selection) => {
+            let out = selection.out(cfg, "epub");
+            let selected = select_files(cfg, selection.under.as_deref(), &selection.files);
+            epub::export(cfg, &selected, &selection.title(cfg), &out)?;
+            (selected.len(), out)
+        }
+        Export::Book(selection) => {
+            let out = selection.out(cfg, "html");
+            // files given on the command line win over the list in config.yaml.
+            let files = if selection.files.is_empty() && selection.under.is_none() {
+                &cfg.user_config.book.files
+            } else {
+                &selection.files
+            };
+            let selected = select_files(cfg, selection.under.as_deref(), files);
+            book::export(cfg, &selected, &selection.title(cfg), &out)?;
+            (selected.len(), out)
+        }
+        Export::Markdown { out } => {
+            let out = out
+                .clone()
+                .unwrap_or_else(|| cfg.dir_firn.join("_exports").join("markdown"));
+            (markdown::export(cfg, &out)?, out)
+        }
+    };
+    println!("Wrote {} files to {}", count, out.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toc_list() {
+        let item =
+            |level: usize, label: &str| (level, label.to_string(), format!("{}.xhtml", label));
+        assert_eq!(
+            "<ol><li><a href=\"a.xhtml\">a</a><ol><li><a href=\"b.xhtml\">b</a><ol><li><a href=\"c.xhtml\">c</a></li></ol></li><li><a href=\"d.xhtml\">d</a></li></ol></li><li><a href=\"e.xhtml\">e</a></li></ol>",
+            toc_list(&[item(0, "a"), item(1, "b"), item(3, "c"), item(1, "d"), item(0, "e")])
+        );
+        assert_eq!("<ol></ol>", toc_list(&[]));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{config::Config, org::OrgFile, templates, util};
+
+use anyhow::{Context, Result};
+use orgize::export::HtmlEscape;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+// -- Feeds ----------------------------------------------------------------------
+//
+// Atom and RSS feeds are built from every public `firn_type: post` file.
+// A feed is first collected into a `Feed` struct, and then written out as both
+// atom and rss so the two never drift apart.
+
+#[derive(Debug, PartialEq)]
+pub struct FeedItem {
+    pub title: String,
+    pub url: String,
+    pub published_ts: Option<i64>,
+    pub updated_ts: Option<i64>,
+    pub content: Option<String>,
+    pub categories: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct Feed {
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub site_url: String,
+    pub self_url: String,
+    pub items: Vec<FeedItem>,
+}
+
+impl FeedItem {
+    pub fn new(file: &OrgFile, cfg: &Config) -> FeedItem {
+        let fm = &file.front_matter;
+        let render = templates::Render::new(file, cfg);
+        let content = if cfg.user_config.feeds.content == "summary" {
+            render
+                .render_headline(&cfg.user_config.feeds.summary_headline, None)
+                .ok()
+        } else {
+            Some(render.render_entire_file(None))
+        };
+
+        FeedItem {
+            title: fm.get_title().to_string(),
+            url: file.full_url.clone(),
+            published_ts: fm.date_created_ts,
+            updated_ts: fm.date_updated_ts.or(fm.date_created_ts),
+            content,
+            categories: fm.firn_tags.clone().unwrap_or_default(),
+        }
+    }
+}
+
+impl Feed {
+    /// the most recent update of all items, used as the feed's own timestamp.
+    fn updated_ts(&self) -> i64 {
+        self.items
+            .iter()
+            .filter_map(|i| i.updated_ts)
+            .max()
+            .unwrap_or_else(|| chrono::Utc::now().timestamp())
+    }
+
+    pub fn to_atom(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        out.push_str(&format!("  <title>{}</title>\n", HtmlEscape(&self.title)));
+        out.push_str(&format!(
+            "  <subtitle>{}</subtitle>\n",
+            HtmlEscape(&self.description)
+        ));
+        out.push_str(&format!(
+            "  <link href=\"{}\" rel=\"self\"/>\n",
+            HtmlEscape(&self.self_url)
+        ));
+        out.push_str(&format!("  <link href=\"{}\"/>\n", HtmlEscape(&self.site_url)));
+        out.push_str(&format!(
+            "  <author><name>{}</name></author>\n",
+            HtmlEscape(&self.author)
+        ));
+        out.push_str(&format!("  <id>{}</id>\n", HtmlEscape(&self.self_url)));
+        out.push_str(&format!(
+            "  <updated>{}</updated>\n",
+            format_rfc3339(self.updated_ts())
+        ));
+        for item in &self.items {
+            let updated = item.updated_ts.unwrap_or_else(|| self.updated_ts());
+            out.push_str("  <entry>\n");
+            out.push_str(&format!("    <title>{}</title>\n", HtmlEscape(&item.title)));
+            out.push_str(&format!("    <link href=\"{}\"/>\n", HtmlEscape(&item.url)));
+            out.push_str(&format!("    <id>{}</id>\n", HtmlEscape(&item.url)));
+            if let Some(published) = item.published_ts {
+                out.push_str(&format!(
+                    "    <published>{}</published>\n",
+                    format_rfc3339(published)
+                ));
+            }
+            out.push_str(&format!("    <updated>{}</updated>\n", format_rfc3339(updated)));
+            for category in &item.categories {
+                out.push_str(&format!("    <category term=\"{}\"/>\n", HtmlEscape(category)));
+            }
+            if let Some(content) = &item.content {
+                out.push_str(&format!(
+                    "    <content type=\"html\">{}</content>\n",
+                    HtmlEscape(content)
+                ));
+            }
+            out.push_str("  </entry>\n");
+        }
+        out.push_str("</feed>\n");
+        out
+    }
+
+    pub fn to_rss(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        out.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+        out.push_str("  <channel>\n");
+        out.push_str(&format!("    <title>{}</title>\n", HtmlEscape(&self.title)));
+        out.push_str(&format!("    <link>{}</link>\n", HtmlEscape(&self.site_url)));
+        out.push_str(&format!(
+            "    <description>{}</description>\n",
+            HtmlEscape(&self.description)
+        ));
+        out.push_str(&format!(
+            "    <lastBuildDate>{}</lastBuildDate>\n",
+            format_rfc2822(self.updated_ts())
+        ));
+        for item in &self.items {
+            out.push_str("    <item>\n");
+            out.push_str(&format!("      <title>{}</title>\n", HtmlEscape(&item.title)));
+            out.push_str(&format!("      <link>{}</link>\n", HtmlEscape(&item.url)));
+            out.push_str(&format!(
+                "      <guid isPermaLink=\"true\">{}</guid>\n",
+                HtmlEscape(&item.url)
+            ));
+            if let Some(published) = item.published_ts {
+                out.push_str(&format!(
+                    "      <pubDate>{}</pubDate>\n",
+                    format_rfc2822(published)
+                ));
+            }
+            for category in &item.categories {
+                out.push_str(&format!(
+                    "      <category>{}</category>\n",
+                    HtmlEscape(category)
+                ));
+            }
+            if let Some(content) = &item.content {
+                out.push_str(&format!(
+                    "      <description>{}</description>\n",
+                    HtmlEscape(content)
+                ));
+            }
+            out.push_str("    </item>\n");
+        }
+        out.push_str("  </channel>\n");
+        out.push_str("</rss>\n");
+        out
+    }
+}
+
+fn format_rfc3339(ts: i64) -> String {
+    util::format_timestamp(ts, "%Y-%m-%dT%H:%M:%SZ")
+}
+
+fn format_rfc2822(ts: i64) -> String {
+    util::format_timestamp(ts, "%a, %d %b %Y %H:%M:%S +0000")
+}
+
+/// Writes a feed as both atom and rss to the _site folder.
+fn write_feed(feed: &Feed, atom_out: PathBuf, rss_out: PathBuf) -> Result<()> {
+    for (out_path, contents) in [(atom_out, feed.to_atom()), (rss_out, feed.to_rss())] {
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create feed directory")?;
+        }
+        fs::write(&out_path, contents)
+            .with_context(|| format!("Failed to write feed: {}", out_path.display()))?;
+    }
+    Ok(())
+}
+
+/// build writes the site wide feeds, and optionally one feed per tag.
+pub fn build(cfg: &Config) -> Result<()> {
+    let feed_cfg = &cfg.user_config.feeds;
+    if !feed_cfg.enabled {
+        return Ok(());
+    }
+    let site = &cfg.user_config.site;
+    let author = if feed_cfg.author.is_empty() {
+        site.title.clone()
+    } else {
+        feed_cfg.author.clone()
+    };
+    let public_posts = cfg.public_posts();
+    let mut posts = public_posts.clone();
+    if feed_cfg.limit > 0 {
+        posts.truncate(feed_cfg.limit);
+    }
+
+    let feed = Feed {
+        title: site.title.clone(),
+        description: site.description.clone(),
+        author: author.clone(),
+        site_url: cfg.base_url.base_url.clone(),
+        self_url: format!("{}/{}", cfg.base_url.base_url, feed_cfg.atom_path),
+        items: posts.iter().map(|p| FeedItem::new(p, cfg)).collect(),
+    };
+    write_feed(
+        &feed,
+        cfg.dir_site_out.join(&feed_cfg.atom_path),
+        cfg.dir_site_out.join(&feed_cfg.rss_path),
+    )?;
+
+    if !feed_cfg.tag_feeds {
+        return Ok(());
+    }
+
+    // -- Tag Feeds: one feed per tag, containing the posts with that tag.
+    for (tag_name, tagged_items) in &cfg.tags_map {
+        let tagged_files: HashSet<&PathBuf> = tagged_items
+            .iter()
+            .map(|t| &t.originating_file_path)
+            .collect();
+        let mut tagged_posts: Vec<_> = public_posts
+            .iter()
+            .filter(|p| tagged_files.contains(&p.file_path))
+            .collect();
+        if tagged_posts.is_empty() {
+            continue;
+        }
+        if feed_cfg.limit > 0 {
+            tagged_posts.truncate(feed_cfg.limit);
+        }
+
+        let tag_dir = PathBuf::from(&feed_cfg.tag_feeds_url).join(tag_name);
+        let atom_path = tag_dir.join(&feed_cfg.atom_path);
+        let tag_feed = Feed {
+            title: format!("{} - {}", site.title, tag_name),
+            description: site.description.clone(),
+            author: author.clone(),
+            site_url: cfg.base_url.base_url.clone(),
+            self_url: format!(
+                "{}/{}",
+                cfg.base_url.base_url,
+                util::path_to_string(&atom_path)
+            ),
+            items: tagged_posts.iter().map(|p| FeedItem::new(p, cfg)).collect(),
+        };
+        write_feed(
+            &tag_feed,
+            cfg.dir_site_out.join(atom_path),
+            cfg.dir_site_out.join(tag_dir.join(&feed_cfg.rss_path)),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_feed() -> Feed {
+        Feed {
+            title: "My Site".to_string(),
+            description: "Notes & things".to_string(),
+            author: "Jo".to_string(),
+            site_url: "https://mysite.com".to_string(),
+            self_url: "https://mysite.com/feed.xml".to_string(),
+            items: vec![FeedItem {
+                title: "First <post>".to_string(),
+                url: "https://mysite.com/blog/first.html".to_string(),
+                published_ts: Some(1641081600),
+                updated_ts: Some(1641168000),
+                content: Some("<p>Hello</p>".to_string()),
+                categories: vec!["rust".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_to_atom() {
+        let atom = test_feed().to_atom();
+        assert!(atom.contains("<subtitle>Notes &amp; things</subtitle>"));
+        assert!(atom.contains("<author><name>Jo</name></author>"));
+        assert!(atom.contains("<title>First &lt;post&gt;</title>"));
+        assert!(atom.contains("<published>2022-01-02T00:00:00Z</published>"));
+        assert!(atom.contains("<updated>2022-01-03T00:00:00Z</updated>"));
+        assert!(atom.contains("<content type=\"html\">&lt;p&gt;Hello&lt;/p&gt;</content>"));
+        assert!(atom.contains("<category term=\"rust\"/>"));
+    }
+
+    #[test]
+    fn test_to_rss() {
+        let rss = test_feed().to_rss();
+        assert!(rss.contains("<link>https://mysite.com</link>"));
+        assert!(rss.contains("<pubDate>Sun, 02 Jan 2022 00:00:00 +0000</pubDate>"));
+        assert!(rss.contains("<lastBuildDate>Mon, 03 Jan 2022 00:00:00 +0000</lastBuildDate>"));
+    }
+}
@@ -0,0 +1,339 @@
+use orgize::elements::FnRef;
+use std::collections::HashMap;
+use std::io::{Result, Write};
+
+// -- Footnotes ------------------------------------------------------------------
+//
+// orgize parses a footnote definition's text as children of the definition,
+// which its own (debug build) validation then rejects. So definitions are
+// taken out of the file before it is parsed, and rendered by us: references
+// (`[fn:1]`, inline `[fn::text]` and named `[fn:name:text]`) become numbered
+// links, and the footnotes referenced in what was rendered are listed after it,
+// each linking back to its references.
+
+/// MODES are the values of `file: footnotes` and `#+firn_footnotes`: "section"
+/// lists footnotes after the file, "sidenotes" writes them where they are referenced.
+pub const MODES: [&str; 2] = ["section", "sidenotes"];
+
+/// is_headline is true for lines starting with stars and a space.
+fn is_headline(line: &str) -> bool {
+    let rest = line.trim_start_matches('*');
+    rest.len() < line.len() && rest.starts_with(' ')
+}
+
+/// definition_start returns the label and text of a line starting a footnote
+/// definition (ex: `[fn:1] My footnote.`).
+fn definition_start(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("[fn:")?;
+    let end = rest.find(']')?;
+    let label = &rest[..end];
+    let valid = !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Some((label, rest[end + 1..].trim()))
+    } else {
+        None
+    }
+}
+
+/// split_definitions takes the footnote definitions out of an org file, and
+/// returns the rest of it, and each definition's label and text. A definition
+/// runs until the next one, the next headline, or two blank lines, so its text
+/// can start on the same line or after a blank line. Headlines are always
+/// kept, so a `* Footnotes` headline stays (empty), with its child headlines.
+pub fn split_definitions(org: &str) -> (String, Vec<(String, String)>) {
+    let mut out: Vec<&str> = Vec::new();
+    let mut definitions: Vec<(String, Vec<&str>)> = Vec::new();
+    let mut in_block = false;
+    let mut in_definition = false;
+    let mut blanks = 0;
+
+    for line in org.lines() {
+        let lower = line.trim_start().to_lowercase();
+        if !in_definition {
+            if lower.starts_with("#+begin_") {
+                in_block = true;
+            } else if lower.starts_with("#+end_") {
+                in_block = false;
+            }
+        }
+        if in_block {
+            out.push(line);
+            continue;
+        }
+
+        if let Some((label, text)) = definition_start(line) {
+            definitions.push((label.to_string(), vec![text]));
+            in_definition = true;
+            blanks = 0;
+            continue;
+        }
+        if is_headline(line) {
+            in_definition = false;
+            out.push(line);
+            continue;
+        }
+        if in_definition {
+            if line.trim().is_empty() {
+                blanks += 1;
+                if blanks == 2 {
+                    in_definition = false;
+                    out.push(line);
+                }
+            } else {
+                blanks = 0;
+            }
+            if in_definition {
+                if let Some((_, text)) = definitions.last_mut() {
+                    text.push(line);
+                }
+            }
+            continue;
+        }
+        out.push(line);
+    }
+
+    let definitions = definitions
+        .into_iter()
+        .map(|(label, text)| (label, text.join("\n").trim().to_string()))
+        .collect();
+    (out.join("\n") + "\n", definitions)
+}
+
+/// Footnote is a referenced footnote, numbered in the order it was first referenced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    pub number: usize,
+    pub label: String,
+    pub html: String,
+    /// how many times it was referenced.
+    pub refs: usize,
+}
+
+/// Footnotes numbers the references met while rendering.
+#[derive(Debug, Default)]
+pub struct Footnotes {
+    notes: Vec<Footnote>,
+    /// `footnotes: sidenotes` writes each footnote where it is referenced,
+    /// for tufte-css style sidenotes, instead of listing them after the file.
+    sidenotes: bool,
+    /// put in the footnotes' ids (ex: `fn-notes-1`), so that they don't collide
+    /// with those of other renders on the same page.
+    id_prefix: String,
+}
+
+/// is_margin_note is true for footnotes labelled `mn` or `mn-...`, which are
+/// written as unnumbered margin notes when footnotes are sidenotes.
+pub fn is_margin_note(label: &str) -> bool {
+    label == "mn" || label.starts_with("mn-")
+}
+
+impl Footnotes {
+    /// new starts numbering footnotes; `id_prefix` is empty, or ends with a `-`.
+    pub fn new(sidenotes: bool, id_prefix: &str) -> Footnotes {
+        Footnotes {
+            sidenotes,
+            id_prefix: id_prefix.to_string(),
+            ..Footnotes::default()
+        }
+    }
+
+    /// is_sidenotes is true when footnotes are written where they are referenced;
+    /// their text is then written without paragraphs, as it sits in a <span>.
+    pub fn is_sidenotes(&self) -> bool {
+        self.sidenotes
+    }
+
+    /// reference counts a reference to `label` (empty for an anonymous inline
+    /// footnote) and returns the footnote's number and the reference's count.
+    /// `html` is called for the footnote's text the first time it is referenced.
+    pub fn reference(&mut self, label: &str, html: impl FnOnce() -> String) -> (usize, usize) {
+        if !label.is_empty() {
+            if let Some(note) = self.notes.iter_mut().find(|n| n.label == label) {
+                note.refs += 1;
+                return (note.number, note.refs);
+            }
+        }
+        let number = self.notes.len() + 1;
+        self.notes.push(Footnote {
+            number,
+            label: label.to_string(),
+            html: html(),
+            refs: 1,
+        });
+        (number, 1)
+    }
+
+    /// write_ref writes the link to a footnote where it is referenced or, for
+    /// sidenotes, the footnote itself.
+    pub fn write_ref<W: Write>(&self, mut w: W, number: usize, count: usize) -> Result<()> {
+        if !self.sidenotes {
+            return write!(
+                w,
+                "<sup class=\"firn-footnote-ref\"><a id=\"{}\" href=\"#{}\">{}</a></sup>",
+                self.id("fnref", number, count),
+                self.id("fn", number, 1),
+                number
+            );
+        }
+        let note = &self.notes[number - 1];
+        if is_margin_note(&note.label) {
+            let id = self.id("mn", number, count);
+            write!(
+                w,
+                "<label for=\"{0}\" class=\"margin-toggle\">&#8853;</label><input type=\"checkbox\" id=\"{0}\" class=\"margin-toggle\"/><span class=\"marginnote\">{1}</span>",
+                id, note.html
+            )
+        } else {
+            let id = self.id("sn", number, count);
+            write!(
+                w,
+                "<label for=\"{0}\" class=\"margin-toggle sidenote-number\"></label><input type=\"checkbox\" id=\"{0}\" class=\"margin-toggle\"/><span class=\"sidenote\">{1}</span>",
+                id, note.html
+            )
+        }
+    }
+
+    /// write_section lists the referenced footnotes, if there are any (and
+    /// they aren't sidenotes).
+    pub fn write_section<W: Write>(&self, mut w: W) -> Result<()> {
+        if self.sidenotes || self.notes.is_empty() {
+            return Ok(());
+        }
+        write!(w, "<section class=\"firn-footnotes\"><ol>")?;
+        for note in &self.notes {
+            write!(
+                w,
+                "<li id=\"{}\">{}",
+                self.id("fn", note.number, 1),
+                note.html
+            )?;
+            for count in 1..=note.refs {
+                write!(
+                    w,
+                    " <a class=\"firn-footnote-backref\" href=\"#{}\">↩</a>",
+                    self.id("fnref", note.number, count)
+                )?;
+            }
+            write!(w, "</li>")?;
+        }
+        write!(w, "</ol></section>")
+    }
+
+    /// id is the id of a footnote's nth reference, or of the footnote itself (`fn`).
+    fn id(&self, kind: &str, number: usize, count: usize) -> String {
+        if count == 1 {
+            format!("{}-{}{}", kind, self.id_prefix, number)
+        } else {
+            format!("{}-{}{}-{}", kind, self.id_prefix, number, count)
+        }
+    }
+}
+
+/// ID_KINDS start the ids of the footnotes' elements, ex: `fnref-1`, `sn-notes-2`.
+pub const ID_KINDS: [&str; 4] = ["fn", "fnref", "sn", "mn"];
+
+/// Labelled collects the footnotes of an export that writes them by label after
+/// the file (markdown, gemtext): the file's definitions, then inline footnotes
+/// as they are referenced, with anonymous ones labelled `anonymous-1`, ...
+#[derive(Debug, Default)]
+pub struct Labelled {
+    /// (label, org text) of each footnote, in order.
+    pub notes: Vec<(String, String)>,
+    anonymous: usize,
+}
+
+impl Labelled {
+    pub fn new(definitions: Vec<(String, String)>) -> Labelled {
+        Labelled {
+            notes: definitions,
+            anonymous: 0,
+        }
+    }
+
+    /// reference returns the label a reference is written with, keeping the
+    /// text of an inline footnote the first time it is met.
+    pub fn reference(&mut self, fn_ref: &FnRef) -> String {
+        let label = if fn_ref.label.is_empty() {
+            self.anonymous += 1;
+            format!("anonymous-{}", self.anonymous)
+        } else {
+            fn_ref.label.to_string()
+        };
+        if let Some(definition) = &fn_ref.definition {
+            if !self.notes.iter().any(|(l, _)| *l == label) {
+                self.notes.push((label.clone(), definition.to_string()));
+            }
+        }
+        label
+    }
+}
+
+/// definitions_map is the definitions by label; the first one wins.
+pub fn definitions_map(definitions: Vec<(String, String)>) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for (label, text) in definitions {
+        map.entry(label).or_insert(text);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_definitions() {
+        let org = "#+title: Notes\nSome text[fn:1], more[fn:two].\n\n[fn:1] On one line.\n[fn:two]\n\nAfter a blank line,\nover two.\n\n\nBack to the text.\n#+begin_src org\n[fn:3] not a definition\n#+end_src\n* Footnotes\n[fn:4] In a footnotes headline.\n** Child\nStill under Footnotes.\n";
+        let (rest, definitions) = split_definitions(org);
+        assert_eq!(
+            "#+title: Notes\nSome text[fn:1], more[fn:two].\n\n\nBack to the text.\n#+begin_src org\n[fn:3] not a definition\n#+end_src\n* Footnotes\n** Child\nStill under Footnotes.\n",
+            rest
+        );
+        assert_eq!(
+            vec![
+                ("1".to_string(), "On one line.".to_string()),
+                (
+                    "two".to_string(),
+                    "After a blank line,\nover two.".to_string()
+                ),
+                ("4".to_string(), "In a footnotes headline.".to_string()),
+            ],
+            definitions
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let mut footnotes = Footnotes::default();
+        assert_eq!((1, 1), footnotes.reference("a", || "A".to_string()));
+        assert_eq!((2, 1), footnotes.reference("", || "inline".to_string()));
+        assert_eq!((1, 2), footnotes.reference("a", || unreachable!()));
+
+        let mut out = Vec::new();
+        footnotes.write_ref(&mut out, 1, 2).unwrap();
+        footnotes.write_section(&mut out).unwrap();
+        assert_eq!(
+            "<sup class=\"firn-footnote-ref\"><a id=\"fnref-1-2\" href=\"#fn-1\">1</a></sup><section class=\"firn-footnotes\"><ol><li id=\"fn-1\">A <a class=\"firn-footnote-backref\" href=\"#fnref-1\">↩</a> <a class=\"firn-footnote-backref\" href=\"#fnref-1-2\">↩</a></li><li id=\"fn-2\">inline <a class=\"firn-footnote-backref\" href=\"#fnref-2\">↩</a></li></ol></section>",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sidenotes() {
+        let mut footnotes = Footnotes::new(true, "notes-");
+        footnotes.reference("1", || "A <b>side</b> note.".to_string());
+        footnotes.reference("mn-aside", || "In the margin.".to_string());
+
+        let mut out = Vec::new();
+        footnotes.write_ref(&mut out, 1, 1).unwrap();
+        footnotes.write_ref(&mut out, 2, 1).unwrap();
+        footnotes.write_section(&mut out).unwrap();
+        assert_eq!(
+            "<label for=\"sn-notes-1\" class=\"margin-toggle sidenote-number\"></label><input type=\"checkbox\" id=\"sn-notes-1\" class=\"margin-toggle\"/><span class=\"sidenote\">A <b>side</b> note.</span><label for=\"mn-notes-2\" class=\"margin-toggle\">&#8853;</label><input type=\"checkbox\" id=\"mn-notes-2\" class=\"margin-toggle\"/><span class=\"marginnote\">In the margin.</span>",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -19,13 +19,23 @@ pub struct FrontMatter {
     pub firn_type: String,
     pub layout: Option<String>,
     pub firn_sitemap: bool,
+    pub firn_search: bool,
+    pub firn_order: Option<i64>,
+    pub firn_image: Option<String>,
+    pub firn_series: Option<String>,
+    pub firn_series_order: Option<i64>,
     pub firn_private: bool,
+    pub firn_draft: bool,
     pub firn_properties: bool,
+    /// overrides `file.fold` for this file.
+    pub firn_fold: Option<bool>,
+    /// overrides `file.footnotes` for this file.
+    pub firn_footnotes: Option<String>,
     pub other: HashMap<String, String>,
 }
 
-impl FrontMatter {
-    pub fn default() -> FrontMatter {
+impl Default for FrontMatter {
+    fn default() -> FrontMatter {
         FrontMatter {
             title: None,
             date_created: None,
@@ -38,12 +48,22 @@ impl FrontMatter {
             firn_type: "page".to_string(),
             layout: None,
             firn_sitemap: true,
+            firn_search: true,
+            firn_order: None,
+            firn_image: None,
+            firn_series: None,
+            firn_series_order: None,
             firn_private: false,
+            firn_draft: false,
             firn_properties: false,
+            firn_fold: None,
+            firn_footnotes: None,
             other: HashMap::new(),
         }
     }
+}
 
+impl FrontMatter {
     pub fn new(parsed: &orgize::Org) -> FrontMatter {
         let mut fm = FrontMatter::default();
         fm.collect(parsed);
@@ -116,8 +136,16 @@ impl FrontMatter {
             "roam_tags" => self.firn_tags = Some(str_to_vec(v)),
             // NOTE: If a boolean based keyword is present at all, that is sufficient to say that it is true
             "firn_private" => self.firn_private = true,
+            "firn_draft" => self.firn_draft = v.trim().parse().unwrap_or(true),
             "firn_sitemap" => self.firn_sitemap = v.parse().unwrap_or(true),
+            "firn_search" => self.firn_search = v.parse().unwrap_or(true),
+            "firn_order" => self.firn_order = v.trim().parse().ok(),
+            "firn_image" => self.firn_image = Some(v.trim().to_string()),
+            "firn_series" => self.firn_series = Some(v.trim().to_string()),
+            "firn_series_order" => self.firn_series_order = v.trim().parse().ok(),
             "firn_properties" => self.firn_properties = true,
+            "firn_fold" => self.firn_fold = Some(v.trim().parse().unwrap_or(true)),
+            "firn_footnotes" => self.firn_footnotes = Some(v.trim().to_string()),
             _ => {
                 self.other.insert(k, v);
             }
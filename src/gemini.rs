@@ -0,0 +1,347 @@
+use crate::{
+    config::{BaseUrl, Config},
+    footnotes,
+    org::{OrgFile, OrgMetadataType},
+    util,
+};
+
+use anyhow::{Context, Result};
+use orgize::{Element, Event, Org};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// -- Gemini ---------------------------------------------------------------------
+//
+// An alternative output target that writes gemtext (.gmi) instead of html.
+// Gemtext is line based: a line is either a heading, a list item, a quote, a
+// link or plain text, and links can't be inline. So the writer below collects
+// each block's text and links, and writes the links out after the block.
+
+#[derive(Default)]
+struct GemtextWriter {
+    out: String,
+    /// the text of the block (paragraph, list item, title) being written.
+    line: String,
+    /// (url, label) of the links in the current block.
+    links: Vec<(String, String)>,
+    list_depth: usize,
+    quote_depth: usize,
+    bullet_pending: bool,
+    in_table: bool,
+    /// the footnotes, written after the file.
+    footnotes: footnotes::Labelled,
+}
+
+impl GemtextWriter {
+    /// flush writes the current block's text, and then its links.
+    fn flush(&mut self) {
+        let text = self.line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            let prefix = if self.bullet_pending {
+                "* "
+            } else if self.quote_depth > 0 {
+                "> "
+            } else {
+                ""
+            };
+            self.out.push_str(&format!("{}{}\n", prefix, text));
+            self.bullet_pending = false;
+        }
+        for (url, label) in self.links.drain(..) {
+            self.out.push_str(&format!("=> {} {}\n", url, label));
+        }
+        if !text.is_empty() && self.list_depth == 0 {
+            self.out.push('\n');
+        }
+        self.line.clear();
+    }
+
+    fn preformatted(&mut self, alt: &str, contents: &str) {
+        self.flush();
+        self.out.push_str(&format!("```{}\n{}", alt, contents));
+        if !contents.ends_with('\n') {
+            self.out.push('\n');
+        }
+        self.out.push_str("```\n\n");
+    }
+
+    fn start(&mut self, element: &Element, resolve_link: &dyn Fn(&str) -> String) {
+        match element {
+            Element::Title(_) => self.flush(),
+            Element::List(_) => {
+                if self.list_depth == 0 {
+                    self.flush();
+                }
+                self.list_depth += 1;
+            }
+            Element::ListItem(_) => {
+                self.flush();
+                self.bullet_pending = true;
+            }
+            Element::QuoteBlock(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            Element::Table(_) => {
+                self.flush();
+                self.in_table = true;
+                self.out.push_str("```\n");
+            }
+            Element::SourceBlock(block) => self.preformatted(&block.language, &block.contents),
+            Element::ExampleBlock(block) => self.preformatted("", &block.contents),
+            Element::FixedWidth(fixed) => self.preformatted("", &fixed.value),
+            Element::Text { value } | Element::Code { value } | Element::Verbatim { value } => {
+                self.line.push_str(value)
+            }
+            Element::InlineSrc(src) => self.line.push_str(&src.body),
+            Element::FnRef(fn_ref) => {
+                let label = self.footnotes.reference(fn_ref);
+                self.line.push_str(&format!("[{}]", label))
+            }
+            Element::Link(link) => {
+                let label = match &link.desc {
+                    Some(desc) => desc.to_string(),
+                    None => link.path.trim_start_matches("file:").to_string(),
+                };
+                // images can't be shown inline, so they're only a link.
+                if !util::org_str_is_img_link(&link.path) {
+                    self.line.push_str(&label);
+                }
+                self.links.push((resolve_link(&link.path), label));
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, element: &Element) {
+        match element {
+            Element::Title(title) => {
+                let text = self.line.split_whitespace().collect::<Vec<_>>().join(" ");
+                // the page title is the only `#`, and gemtext only has three levels of headings.
+                let hashes = "#".repeat((title.level + 1).min(3));
+                self.out.push_str(&format!("{} {}\n", hashes, text));
+                self.line.clear();
+                self.flush();
+                self.out.push('\n');
+            }
+            Element::Paragraph { .. } if !self.in_table => self.flush(),
+            Element::ListItem(_) => self.flush(),
+            Element::List(_) => {
+                self.list_depth -= 1;
+                if self.list_depth == 0 {
+                    self.out.push('\n');
+                }
+            }
+            Element::QuoteBlock(_) => {
+                self.flush();
+                self.quote_depth -= 1;
+            }
+            Element::TableCell(_) => self.line.push_str(" | "),
+            Element::TableRow(_) => {
+                let row = self.line.trim_end_matches(&[' ', '|'][..]).to_string();
+                if !row.is_empty() {
+                    self.out.push_str(&format!("| {} |\n", row.trim()));
+                }
+                self.line.clear();
+            }
+            Element::Table(_) => {
+                self.in_table = false;
+                self.out.push_str("```\n\n");
+            }
+            Element::Document { .. } => self.flush(),
+            _ => {}
+        }
+    }
+}
+
+/// to_gemtext walks the parsed org content, the way MyHtmlHandler does for
+/// html, and writes it out as gemtext, followed by a section of its footnotes
+/// (the file's `definitions`, see footnotes.rs, and inline ones). Links are
+/// resolved with `resolve_link`.
+pub fn to_gemtext(
+    parsed: &Org,
+    definitions: Vec<(String, String)>,
+    resolve_link: &dyn Fn(&str) -> String,
+) -> String {
+    let mut writer = GemtextWriter {
+        footnotes: footnotes::Labelled::new(definitions),
+        ..GemtextWriter::default()
+    };
+    for event in parsed.iter() {
+        match event {
+            Event::Start(element) => writer.start(element, resolve_link),
+            Event::End(element) => writer.end(element),
+        }
+    }
+    let mut out = writer.out.trim_end().to_string() + "\n";
+    if !writer.footnotes.notes.is_empty() {
+        out.push_str("\n## Footnotes\n\n");
+    }
+    for (label, text) in &writer.footnotes.notes {
+        let note = to_gemtext(&Org::parse(text), vec![], resolve_link);
+        out.push_str(&format!("[{}] {}", label, note.trim_start()));
+    }
+    out
+}
+
+/// gemini_url is the url of a web path (ex: blog/post.html) on the gemini capsule.
+fn gemini_url(cfg: &Config, web_path: &Path) -> String {
+    format!(
+        "{}/{}",
+        cfg.user_config.gemini.url,
+        util::path_to_string(&web_path.with_extension("gmi"))
+    )
+}
+
+/// gemini_link resolves a link like the html build does, but against the
+/// gemini url, and to .gmi files rather than .html files.
+fn gemini_link(base_url: &BaseUrl, link: &str, file_path: &Path) -> String {
+    let is_org_file = util::is_local_org_file(link);
+    let url = util::transform_org_link_to_html(
+        base_url.clone(),
+        link.to_string(),
+        file_path.to_path_buf(),
+    );
+    if is_org_file && url.ends_with(".html") {
+        format!("{}.gmi", url.trim_end_matches(".html"))
+    } else {
+        url
+    }
+}
+
+fn write_page(out_path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create gemini directory")?;
+    }
+    fs::write(out_path, contents).context("Failed to write gemini file")
+}
+
+fn write_file(base_url: &BaseUrl, dir_out: &Path, file: &OrgFile) -> Result<()> {
+    let body = to_gemtext(
+        &file.parsed,
+        footnotes::split_definitions(&file.original_org).1,
+        &|link| gemini_link(base_url, link, &file.file_path),
+    );
+    let page = format!("# {}\n\n{}", file.front_matter.get_title(), body);
+    write_page(&dir_out.join(file.web_path.with_extension("gmi")), &page)
+}
+
+/// index_pages writes a gemtext page for each tag, and the sitemap (with the tags).
+fn index_pages(cfg: &Config, dir_out: &Path) -> Result<()> {
+    let mut tag_names: Vec<&String> = cfg.tags_map.keys().collect();
+    tag_names.sort();
+    let tag_web_path =
+        |tag_name: &str| PathBuf::from(format!("{}{}.gmi", cfg.user_config.tags.url, tag_name));
+
+    for tag_name in &tag_names {
+        let mut page = format!("# {}\n\n", tag_name);
+        let mut seen: Vec<String> = Vec::new();
+        for item in &cfg.tags_map[*tag_name] {
+            let label = match &item.originating_headline {
+                Some(headline) => format!("{} > {}", item.originating_file, headline),
+                None => item.originating_file.clone(),
+            };
+            let line = format!(
+                "=> {} {}\n",
+                gemini_url(cfg, &item.originating_file_web_path),
+                label
+            );
+            if !seen.contains(&line) {
+                page.push_str(&line);
+                seen.push(line);
+            }
+        }
+        write_page(&dir_out.join(tag_web_path(tag_name)), &page)?;
+    }
+
+    let mut sitemap: Vec<_> = cfg
+        .global_sitemap
+        .values()
+        .filter(|v| matches!(v.entity, OrgMetadataType::Sitemap(_)))
+        .collect();
+    sitemap.sort_by_key(|v| v.originating_file.clone());
+    let mut page = format!("# {}\n\n", cfg.user_config.site.title);
+    for item in sitemap {
+        page.push_str(&format!(
+            "=> {} {}\n",
+            gemini_url(cfg, &item.originating_file_web_path),
+            item.originating_file
+        ));
+    }
+    if !tag_names.is_empty() {
+        page.push_str("\n## Tags\n\n");
+        for tag_name in &tag_names {
+            page.push_str(&format!(
+                "=> {}/{} {}\n",
+                cfg.user_config.gemini.url,
+                util::path_to_string(&tag_web_path(tag_name)),
+                tag_name
+            ));
+        }
+    }
+    write_page(&dir_out.join("sitemap.gmi"), &page)
+}
+
+/// build writes every public file, the tag pages and the sitemap as
+/// gemtext, and copies the data directory alongside them.
+pub fn build(cfg: &Config) -> Result<()> {
+    let gemini_cfg = &cfg.user_config.gemini;
+    if !gemini_cfg.enabled {
+        return Ok(());
+    }
+
+    let dir_out = cfg.dir_firn.join(&gemini_cfg.path);
+    let base_url = BaseUrl::new(
+        gemini_cfg.url.clone(),
+        cfg.dir_source.clone(),
+        cfg.dir_data_files_src.clone(),
+    );
+    for file in &cfg.org_files {
+        if file.front_matter.title.is_some()
+            && !file.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+        {
+            write_file(&base_url, &dir_out, file)?;
+        }
+    }
+    index_pages(cfg, &dir_out)?;
+
+    if cfg.dir_data_files_src.exists() {
+        Command::new("cp")
+            .arg("-r")
+            .arg(cfg.dir_data_files_src.display().to_string())
+            .arg(dir_out.display().to_string())
+            .output()
+            .context("Failed to copy data directory to the gemini directory")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_gemtext() {
+        let parsed = Org::parse(
+            "#+title: Test\n\nIntro with a [[file:other.org][link]]\nover two lines.\n\n* First\n- one\n- two [[https://example.com][web]]\n\n** Second\n#+begin_src rust\nfn main() {}\n#+end_src\n\n#+begin_quote\nA quote.\n#+end_quote\n\n**** Deep\n[[file:cat.png]]\n",
+        );
+        let gemtext = to_gemtext(&parsed, vec![], &|link| format!("gemini://x/{}", link));
+        assert_eq!(
+            "Intro with a link over two lines.\n=> gemini://x/file:other.org link\n\n## First\n\n* one\n* two web\n=> gemini://x/https://example.com web\n\n### Second\n\n```rust\nfn main() {}\n```\n\n> A quote.\n\n### Deep\n\n=> gemini://x/file:cat.png cat.png\n",
+            gemtext
+        );
+    }
+
+    #[test]
+    fn test_footnotes() {
+        let (org, definitions) = footnotes::split_definitions(
+            "Defined[fn:1] and inline[fn::An [[https://example.com][inline]] note.].\n\n[fn:1] On\ntwo lines.\n",
+        );
+        let gemtext = to_gemtext(&Org::parse(&org), definitions, &|link| link.to_string());
+        assert_eq!(
+            "Defined[1] and inline[anonymous-1].\n\n## Footnotes\n\n[1] On two lines.\n[anonymous-1] An inline note.\n=> https://example.com inline\n",
+            gemtext
+        );
+    }
+}
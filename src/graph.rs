@@ -0,0 +1,233 @@
+use crate::{
+    config::Config,
+    org::{OrgFile, OrgMetadataType},
+    util,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+
+// -- Graph ----------------------------------------------------------------------
+//
+// The file-to-file link graph, built from the global links. Nodes and edges
+// are keyed by url, so templates (and any js that draws the graph) can use
+// them directly.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GraphNode {
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub backlinks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub headline: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// file_tags lists a file's tags (firn and org), lowercased and without duplicates.
+pub fn file_tags(file: &OrgFile) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in &file.tags {
+        if let OrgMetadataType::Tag(tag_name, _) = &tag.entity {
+            let tag_name = tag_name.to_lowercase();
+            if !tags.contains(&tag_name) {
+                tags.push(tag_name);
+            }
+        }
+    }
+    tags
+}
+
+impl Graph {
+    /// new builds the graph of every public file, and every link between them.
+    pub fn new(cfg: &Config) -> Graph {
+        let mut nodes: Vec<GraphNode> = cfg
+            .org_files
+            .iter()
+            .filter(|f| {
+                f.front_matter.title.is_some()
+                    && !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+            })
+            .map(|f| GraphNode {
+                title: f.front_matter.get_title().to_string(),
+                url: f.full_url.clone(),
+                tags: file_tags(f),
+                backlinks: 0,
+            })
+            .collect();
+        let urls: HashSet<String> = nodes.iter().map(|n| n.url.clone()).collect();
+
+        let mut edges: Vec<GraphEdge> = Vec::new();
+        let mut seen: HashSet<GraphEdge> = HashSet::new();
+        for g_link in &cfg.global_links {
+            if let OrgMetadataType::Link(link) = &g_link.entity {
+                if !util::is_local_org_file(&link.path) {
+                    continue;
+                }
+                let target = util::transform_org_link_to_html(
+                    cfg.base_url.clone(),
+                    link.path.to_string(),
+                    g_link.originating_file_path.clone(),
+                );
+                let source = format!("{}/{}", cfg.base_url.base_url, g_link.get_web_path_as_str());
+                let edge = GraphEdge {
+                    source,
+                    target,
+                    headline: g_link.originating_headline.clone(),
+                };
+                if urls.contains(&edge.source)
+                    && urls.contains(&edge.target)
+                    && seen.insert(edge.clone())
+                {
+                    edges.push(edge);
+                }
+            }
+        }
+
+        // backlinks are counted per linking file, not per link.
+        for node in nodes.iter_mut() {
+            let sources: HashSet<&String> = edges
+                .iter()
+                .filter(|e| e.target == node.url && e.source != node.url)
+                .map(|e| &e.source)
+                .collect();
+            node.backlinks = sources.len();
+        }
+        nodes.sort_by(|a, b| a.url.cmp(&b.url));
+
+        Graph { nodes, edges }
+    }
+
+    /// neighbourhood returns the part of the graph within `hops` links of
+    /// `url`, following links in either direction.
+    pub fn neighbourhood(&self, url: &str, hops: usize) -> Graph {
+        let mut seen: HashSet<&str> = HashSet::new();
+        if self.nodes.iter().any(|n| n.url == url) {
+            seen.insert(url);
+        }
+        let mut frontier: HashSet<&str> = seen.clone();
+        for _ in 0..hops {
+            let mut next = HashSet::new();
+            for edge in &self.edges {
+                for (from, to) in [(&edge.source, &edge.target), (&edge.target, &edge.source)] {
+                    if frontier.contains(from.as_str()) && seen.insert(to.as_str()) {
+                        next.insert(to.as_str());
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        Graph {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|n| seen.contains(n.url.as_str()))
+                .cloned()
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|e| seen.contains(e.source.as_str()) && seen.contains(e.target.as_str()))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out = String::from("digraph firn {\n");
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "  \"{}\" [label=\"{}\"];\n",
+                escape(&node.url),
+                escape(&node.title)
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "  \"{}\" -> \"{}\";\n",
+                escape(&edge.source),
+                escape(&edge.target)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// build writes graph.json and, optionally, graph.dot to the _site folder.
+pub fn build(cfg: &Config) -> Result<()> {
+    let graph_cfg = &cfg.user_config.graph;
+    if graph_cfg.json {
+        let json = serde_json::to_string(&cfg.graph).context("Failed to serialize graph")?;
+        fs::write(cfg.dir_site_out.join("graph.json"), json).context("Failed to write graph.json")?;
+    }
+    if graph_cfg.dot {
+        fs::write(cfg.dir_site_out.join("graph.dot"), cfg.graph.to_dot())
+            .context("Failed to write graph.dot")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(url: &str) -> GraphNode {
+        GraphNode {
+            title: url.to_uppercase(),
+            url: url.to_string(),
+            tags: vec![],
+            backlinks: 0,
+        }
+    }
+
+    fn edge(source: &str, target: &str) -> GraphEdge {
+        GraphEdge {
+            source: source.to_string(),
+            target: target.to_string(),
+            headline: None,
+        }
+    }
+
+    #[test]
+    fn test_neighbourhood() {
+        // a -> b -> c -> d, and e <- b
+        let graph = Graph {
+            nodes: vec![node("a"), node("b"), node("c"), node("d"), node("e")],
+            edges: vec![edge("a", "b"), edge("b", "c"), edge("c", "d"), edge("b", "e")],
+        };
+        let urls = |g: Graph| g.nodes.into_iter().map(|n| n.url).collect::<Vec<_>>();
+
+        assert_eq!(vec!["c"], urls(graph.neighbourhood("c", 0)));
+        assert_eq!(vec!["b", "c", "d"], urls(graph.neighbourhood("c", 1)));
+        assert_eq!(vec!["a", "b", "c", "d", "e"], urls(graph.neighbourhood("c", 2)));
+        assert_eq!(2, graph.neighbourhood("c", 1).edges.len());
+        assert!(graph.neighbourhood("missing", 2).nodes.is_empty());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let graph = Graph {
+            nodes: vec![node("a"), node("b\"")],
+            edges: vec![edge("a", "b\"")],
+        };
+        assert_eq!(
+            "digraph firn {\n  \"a\" [label=\"A\"];\n  \"b\\\"\" [label=\"B\\\"\"];\n  \"a\" -> \"b\\\"\";\n}\n",
+            graph.to_dot()
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{config::Config, user_config::FileConfig};
+
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+use orgize::elements::SourceBlock;
+use orgize::export::HtmlEscape;
+use orgize::syntect::highlighting::ThemeSet;
+use orgize::syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use orgize::syntect::parsing::SyntaxSet;
+use orgize::syntect::util::LinesWithEndings;
+use std::fs;
+use std::io::Write;
+use std::sync::OnceLock;
+
+// -- Highlight ------------------------------------------------------------------
+//
+// Src blocks are highlighted with syntect's classes (prefixed with `syn-`), so
+// the colours come from a stylesheet: `firn css syntax --theme <name>` writes
+// one for any of syntect's themes to static/css/syntax.css. Blocks can also get
+// line numbers (`-n`, or `file: src_line_numbers`), highlighted lines
+// (`:hl_lines 2,4-5`) and a caption (`:file name`, or the block's `#+name`).
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "syn-" };
+
+#[derive(Subcommand)]
+pub enum Css {
+    /// Write the stylesheet for highlighted src blocks to static/css/syntax.css
+    Syntax {
+        /// The syntect theme to use (ex: "base16-ocean.dark", "Solarized (light)")
+        #[clap(long, default_value = "InspiredGitHub")]
+        theme: String,
+    },
+}
+
+/// theme_css is the stylesheet for highlighted src blocks in a syntect theme.
+pub fn theme_css(theme: &str) -> Result<String> {
+    let themes = ThemeSet::load_defaults();
+    match themes.themes.get(theme) {
+        Some(found) => Ok(css_for_theme_with_class_style(found, CLASS_STYLE)),
+        None => {
+            let names: Vec<&String> = themes.themes.keys().collect();
+            bail!("No syntax theme named {:?}; try one of {:?}", theme, names)
+        }
+    }
+}
+
+pub fn run(cfg: &Config, css: &Css) -> Result<()> {
+    match css {
+        Css::Syntax { theme } => {
+            let contents = theme_css(theme)?;
+            let out_path = cfg.dir_static_src.join("css").join("syntax.css");
+            fs::create_dir_all(out_path.parent().expect("css has a parent"))
+                .context("Failed to create static/css")?;
+            fs::write(&out_path, contents).context("Failed to write syntax.css")?;
+            println!("Wrote the {:?} theme to {}", theme, out_path.display());
+        }
+    }
+    Ok(())
+}
+
+/// SrcConfig is how src blocks are written, from the site's FileConfig.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SrcConfig {
+    pub highlight: bool,
+    pub line_numbers: bool,
+    pub captions: bool,
+}
+
+impl SrcConfig {
+    pub fn new(file: &FileConfig) -> SrcConfig {
+        SrcConfig {
+            highlight: file.src_highlight,
+            line_numbers: file.src_line_numbers,
+            captions: file.src_captions,
+        }
+    }
+}
+
+/// BlockArgs are the switches and header arguments of a src block that change
+/// how it is presented (ex: `#+begin_src rust -n 10 :hl_lines 2-3 :file main.rs`).
+#[derive(Debug, Default, PartialEq)]
+struct BlockArgs {
+    /// where line numbers start, if the block has `-n` (or `+n`).
+    line_numbers: Option<usize>,
+    hl_lines: Vec<(usize, usize)>,
+    file: Option<String>,
+}
+
+/// parse_ranges reads line ranges like `1,3-4`.
+fn parse_ranges(value: &str) -> Vec<(usize, usize)> {
+    value
+        .split(',')
+        .filter_map(|range| match range.split_once('-') {
+            Some((from, to)) => Some((from.trim().parse().ok()?, to.trim().parse().ok()?)),
+            None => {
+                let line = range.trim().parse().ok()?;
+                Some((line, line))
+            }
+        })
+        .collect()
+}
+
+impl BlockArgs {
+    fn parse(arguments: &str) -> BlockArgs {
+        let mut args = BlockArgs::default();
+        let mut tokens = arguments.split_whitespace().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                "-n" | "+n" => {
+                    let start = tokens.peek().and_then(|t| t.parse().ok());
+                    if start.is_some() {
+                        tokens.next();
+                    }
+                    args.line_numbers = Some(start.unwrap_or(1));
+                }
+                ":hl_lines" => args.hl_lines = tokens.next().map(parse_ranges).unwrap_or_default(),
+                ":file" => args.file = tokens.next().map(|t| t.to_string()),
+                ":tangle" if args.file.is_none() => {
+                    args.file = tokens
+                        .next()
+                        .filter(|t| *t != "yes" && *t != "no")
+                        .map(|t| t.to_string())
+                }
+                _ => {}
+            }
+        }
+        args
+    }
+}
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// highlight returns the code as classed html, if syntect knows the language.
+fn highlight(language: &str, code: &str) -> Option<String> {
+    let syntax_set = syntax_set();
+    let syntax = syntax_set.find_syntax_by_token(language)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator.parse_html_for_line_which_includes_newline(line);
+    }
+    Some(generator.finalize())
+}
+
+/// split_lines splits highlighted html into lines that each close the spans
+/// still open at their end, and reopen them at the start of the next line.
+fn split_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with("</span>") {
+            open.pop();
+            line.push_str("</span>");
+            rest = &rest["</span>".len()..];
+        } else if rest.starts_with("<span") {
+            let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+            open.push(&rest[..end]);
+            line.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix('\n') {
+            line.push_str(&"</span>".repeat(open.len()));
+            lines.push(std::mem::take(&mut line));
+            line.push_str(&open.concat());
+            has_text = false;
+            rest = after;
+        } else {
+            let end = rest.find(['<', '\n']).unwrap_or(rest.len());
+            line.push_str(&rest[..end]);
+            has_text = true;
+            rest = &rest[end..];
+        }
+    }
+    // the code ends with a newline, so the last line is usually only spans.
+    if has_text {
+        line.push_str(&"</span>".repeat(open.len()));
+        lines.push(line);
+    }
+    lines
+}
+
+/// write_src_block writes a src block; `name` is its `#+name`, if it has one.
+pub fn write_src_block<W: Write>(
+    mut w: W,
+    block: &SourceBlock,
+    name: Option<&str>,
+    cfg: &SrcConfig,
+) -> std::io::Result<()> {
+    let args = BlockArgs::parse(&block.arguments);
+    let caption = if cfg.captions {
+        args.file.as_deref().or(name)
+    } else {
+        None
+    };
+    let first_line = args
+        .line_numbers
+        .or(if cfg.line_numbers { Some(1) } else { None });
+    let highlighted = if cfg.highlight {
+        highlight(&block.language, &block.contents)
+    } else {
+        None
+    };
+
+    if let Some(caption) = caption {
+        write!(
+            w,
+            "<figure class=\"firn-src\"><figcaption class=\"firn-src-caption\">{}</figcaption>",
+            HtmlEscape(caption)
+        )?;
+    }
+    write!(
+        w,
+        "<div class=\"org-src-container\"><pre class=\"src src-{}{}\">",
+        block.language,
+        if highlighted.is_some() {
+            " syn-code"
+        } else {
+            ""
+        }
+    )?;
+    if first_line.is_none() && args.hl_lines.is_empty() {
+        match &highlighted {
+            Some(html) => write!(w, "{}", html)?,
+            None => write!(w, "{}", HtmlEscape(&block.contents))?,
+        }
+    } else {
+        let lines = match &highlighted {
+            Some(html) => split_lines(html),
+            None => block
+                .contents
+                .lines()
+                .map(|l| HtmlEscape(l).to_string())
+                .collect(),
+        };
+        for (i, line) in lines.iter().enumerate() {
+            let hl = args
+                .hl_lines
+                .iter()
+                .any(|(from, to)| (*from..=*to).contains(&(i + 1)));
+            write!(
+                w,
+                "<span class=\"firn-src-line{}\">",
+                if hl { " firn-src-hl" } else { "" }
+            )?;
+            if let Some(first_line) = first_line {
+                write!(
+                    w,
+                    "<span class=\"firn-src-lineno\">{}</span>",
+                    first_line + i
+                )?;
+            }
+            writeln!(w, "{}</span>", line)?;
+        }
+    }
+    write!(w, "</pre></div>")?;
+    if caption.is_some() {
+        write!(w, "</figure>")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_args() {
+        assert_eq!(
+            BlockArgs {
+                line_numbers: Some(10),
+                hl_lines: vec![(2, 3), (5, 5)],
+                file: Some("main.rs".to_string()),
+            },
+            BlockArgs::parse("-n 10 :hl_lines 2-3,5 :file main.rs")
+        );
+        assert_eq!(
+            BlockArgs {
+                line_numbers: Some(1),
+                ..BlockArgs::default()
+            },
+            BlockArgs::parse("-n :tangle yes :exports code")
+        );
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let html =
+            "<span class=\"a\">one\n<span class=\"b\">two</span>\nthree\n</span><span class=\"c\"></span>";
+        assert_eq!(
+            vec![
+                "<span class=\"a\">one</span>",
+                "<span class=\"a\"><span class=\"b\">two</span></span>",
+                "<span class=\"a\">three</span>",
+            ],
+            split_lines(html)
+        );
+    }
+
+    #[test]
+    fn test_write_src_block() {
+        let block = SourceBlock {
+            contents: "fn main() {\n    x < y;\n}\n".into(),
+            language: "rust".into(),
+            arguments: ":hl_lines 2".into(),
+            post_blank: 0,
+        };
+        let write = |name: Option<&str>, cfg: &SrcConfig| {
+            let mut out = Vec::new();
+            write_src_block(&mut out, &block, name, cfg).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let plain = write(
+            Some("example"),
+            &SrcConfig {
+                line_numbers: true,
+                ..SrcConfig::default()
+            },
+        );
+        assert_eq!(
+            "<div class=\"org-src-container\"><pre class=\"src src-rust\"><span class=\"firn-src-line\"><span class=\"firn-src-lineno\">1</span>fn main() {</span>\n<span class=\"firn-src-line firn-src-hl\"><span class=\"firn-src-lineno\">2</span>    x &lt; y;</span>\n<span class=\"firn-src-line\"><span class=\"firn-src-lineno\">3</span>}</span>\n</pre></div>",
+            plain
+        );
+
+        let highlighted = write(
+            Some("example"),
+            &SrcConfig {
+                highlight: true,
+                captions: true,
+                ..SrcConfig::default()
+            },
+        );
+        assert!(highlighted.starts_with("<figure class=\"firn-src\"><figcaption class=\"firn-src-caption\">example</figcaption><div class=\"org-src-container\"><pre class=\"src src-rust syn-code\"><span class=\"firn-src-line\"><span class=\"syn-source syn-rust\">"));
+        assert!(highlighted.contains("<span class=\"firn-src-line firn-src-hl\">"));
+        assert!(highlighted.contains("&lt;"));
+        assert!(highlighted.ends_with("</pre></div></figure>"));
+    }
+}
@@ -1,4 +1,7 @@
 use crate::config::BaseUrl;
+use crate::front_matter::FrontMatter;
+use crate::highlight::{self, SrcConfig};
+use crate::user_config::{PropertiesConfig, UserConfig};
 use crate::util;
 use orgize::export::{DefaultHtmlHandler, HtmlEscape, HtmlHandler};
 use orgize::{elements, Element};
@@ -32,14 +35,121 @@ impl From<FromUtf8Error> for MyError {
 }
 
 #[derive(Default)]
-pub struct MyHtmlHandler(DefaultHtmlHandler);
+pub struct MyHtmlHandler {
+    inner: DefaultHtmlHandler,
+    /// set for files with `#+firn_properties`: headline properties are rendered,
+    /// and only the allowed drawers are.
+    properties: Option<PropertiesConfig>,
+    /// while in a hidden drawer, nothing is written.
+    in_hidden_drawer: bool,
+    /// when folding, headlines are wrapped in <details>, and this many levels start open.
+    fold_open_depth: Option<usize>,
+    /// the <details> currently open, and whether each one opens everything under it.
+    folds: Vec<bool>,
+    /// how src blocks are highlighted, numbered and captioned.
+    src: SrcConfig,
+    /// the `#+name` of the src block that (may) come next.
+    src_name: Option<String>,
+}
+
+impl MyHtmlHandler {
+    pub fn new(front_matter: &FrontMatter, user_config: &UserConfig) -> MyHtmlHandler {
+        MyHtmlHandler {
+            properties: if front_matter.firn_properties {
+                Some(user_config.properties.clone())
+            } else {
+                None
+            },
+            fold_open_depth: if front_matter.firn_fold.unwrap_or(user_config.file.fold) {
+                Some(user_config.file.fold_open_depth)
+            } else {
+                None
+            },
+            src: SrcConfig::new(&user_config.file),
+            ..MyHtmlHandler::default()
+        }
+    }
+
+    /// start_fold opens a headline's <details>. It starts open within the open
+    /// depth, unless its `:VISIBILITY:` property says otherwise.
+    fn start_fold<W: Write>(
+        &mut self,
+        mut w: W,
+        title: &elements::Title,
+        open_depth: usize,
+    ) -> Result<(), MyError> {
+        let visibility = title
+            .properties
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("VISIBILITY"))
+            .map(|(_, v)| v.to_lowercase());
+        let parent_open_all = self.folds.last().copied().unwrap_or(false);
+        let open = match visibility.as_deref() {
+            Some("folded") => false,
+            Some("children") | Some("content") | Some("all") => true,
+            _ => parent_open_all || self.folds.len() < open_depth,
+        };
+        let open_all =
+            parent_open_all || matches!(visibility.as_deref(), Some("content") | Some("all"));
+        self.folds.push(open_all);
+        write!(
+            w,
+            "<details class=\"firn-fold\"{}><summary>",
+            if open { " open" } else { "" }
+        )?;
+        Ok(())
+    }
+
+    /// write_properties writes a headline's properties as a definition list,
+    /// leaving out the hidden keys.
+    fn write_properties<W: Write>(
+        &self,
+        mut w: W,
+        title: &elements::Title,
+        cfg: &PropertiesConfig,
+    ) -> Result<(), MyError> {
+        let shown: Vec<_> = title
+            .properties
+            .iter()
+            .filter(|(k, _)| !cfg.hidden.iter().any(|h| h.eq_ignore_ascii_case(k)))
+            .collect();
+        if shown.is_empty() {
+            return Ok(());
+        }
+        write!(w, "<dl class=\"firn-properties\">")?;
+        for (k, v) in shown {
+            write!(
+                w,
+                "<dt class=\"firn-property-key\">{}</dt><dd class=\"firn-property-value\">{}</dd>",
+                HtmlEscape(k),
+                HtmlEscape(v)
+            )?;
+        }
+        write!(w, "</dl>")?;
+        Ok(())
+    }
+}
 
 // this handles the actual writing of html.
 impl HtmlHandler<MyError> for MyHtmlHandler {
     fn start<W: Write>(&mut self, mut w: W, element: &Element) -> Result<(), MyError> {
+        if self.in_hidden_drawer {
+            return Ok(());
+        }
+        // a `#+name` names the element right after it.
+        let src_name = self.src_name.take();
         match element {
             Element::Document { .. } => write!(w, "<div>")?,
+            Element::Keyword(keyword) if keyword.key.eq_ignore_ascii_case("NAME") => {
+                self.src_name = Some(keyword.value.to_string());
+            }
+            Element::SourceBlock(block) if !block.language.is_empty() => {
+                highlight::write_src_block(w, block, src_name.as_deref(), &self.src)?
+            }
             Element::Title(title) => {
+                if let Some(open_depth) = self.fold_open_depth {
+                    self.start_fold(&mut w, title, open_depth)?;
+                }
                 if title.level > 6 {
                     if let Some(keyword) = &title.keyword {
                         write!(w, "<h6 class=\"firn-{1}\" id=\"{0}\">", &title.raw, keyword)?;
@@ -57,7 +167,7 @@ impl HtmlHandler<MyError> for MyHtmlHandler {
                 }
             }
             Element::Link(link) => {
-                let link_path = &link.path.to_owned().to_string();
+                let link_path = &link.path.to_string();
                 if util::org_str_is_img_link(link_path) {
                     write!(w, "<img src=\"{}\"/>", HtmlEscape(&link.path))?
                 } else {
@@ -69,19 +179,54 @@ impl HtmlHandler<MyError> for MyHtmlHandler {
                     )?
                 }
             }
-            _ => self.0.start(w, element)?,
+            Element::Drawer(drawer) if self.properties.is_some() => {
+                let allowed = self.properties.as_ref().is_some_and(|cfg| {
+                    cfg.drawers
+                        .iter()
+                        .any(|d| d.eq_ignore_ascii_case(&drawer.name))
+                });
+                if allowed {
+                    write!(
+                        w,
+                        "<div class=\"firn-drawer firn-drawer-{}\">",
+                        drawer.name.to_lowercase()
+                    )?;
+                } else {
+                    self.in_hidden_drawer = true;
+                }
+            }
+            _ => self.inner.start(w, element)?,
         }
 
         Ok(())
     }
 
     fn end<W: Write>(&mut self, mut w: W, element: &Element) -> Result<(), MyError> {
+        if self.in_hidden_drawer {
+            if let Element::Drawer(_) = element {
+                self.in_hidden_drawer = false;
+            }
+            return Ok(());
+        }
         match element {
             Element::Document { .. } => write!(w, "</div>")?,
             Element::Title(title) => {
-                write!(w, "</h{}>", title.level)?;
+                write!(w, "</h{}>", title.level.min(6))?;
+                if self.fold_open_depth.is_some() {
+                    write!(w, "</summary>")?;
+                }
+                if let Some(cfg) = &self.properties {
+                    self.write_properties(w, title, cfg)?;
+                }
             }
-            _ => self.0.end(w, element)?,
+            Element::Drawer(_) if self.properties.is_some() => write!(w, "</div>")?,
+            Element::Headline { .. } => {
+                // a headline rendered on its own (ex: `render(headline=...)`) has no <details> open.
+                if self.folds.pop().is_some() {
+                    write!(w, "</details>")?;
+                }
+            }
+            _ => self.inner.end(w, element)?,
         }
 
         Ok(())
@@ -110,7 +255,7 @@ pub fn write_link(
     file_path: PathBuf,
 ) {
     let link_web_path =
-        util::transform_org_link_to_html(base_url, link.path.to_owned().to_string(), file_path);
+        util::transform_org_link_to_html(base_url, link.path.to_string(), file_path);
     let new_link = elements::Link {
         path: std::borrow::Cow::Borrowed(&link_web_path),
         desc: link.desc.to_owned(),
@@ -119,24 +264,113 @@ pub fn write_link(
     handler.start(writer, &new_link_enum).unwrap()
 }
 
+/// with_level returns a copy of `title`, moved `update_level` levels (between 1 and 6).
+fn with_level<'a>(title: &elements::Title<'a>, update_level: Option<i8>) -> elements::Title<'a> {
+    let update_level = update_level.unwrap_or(0);
+    let new_level = (title.level as i8 + update_level).clamp(1, 6);
+
+    elements::Title {
+        level: new_level as usize,
+        ..title.to_owned()
+    }
+}
+
 pub fn write_title(
     title: &elements::Title,
     handler: &mut MyHtmlHandler,
     writer: &mut Vec<u8>,
     update_level: Option<i8>,
 ) {
-    let update_level = update_level.unwrap_or(0);
-    let mut new_level = title.level as i8 + update_level;
-    if new_level > 6 {
-        new_level = 6
-    } else if new_level < 1 {
-        new_level = 1
+    let new_title = Element::Title(with_level(title, update_level));
+    handler.start(writer, &new_title).unwrap()
+}
+
+/// write_title_end closes a title opened by write_title, at the same level.
+pub fn write_title_end(
+    title: &elements::Title,
+    handler: &mut MyHtmlHandler,
+    writer: &mut Vec<u8>,
+    update_level: Option<i8>,
+) {
+    let new_title = Element::Title(with_level(title, update_level));
+    handler.end(writer, &new_title).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orgize::Org;
+
+    fn write_html(org: &str, mut handler: MyHtmlHandler) -> String {
+        let mut wr = Vec::new();
+        Org::parse(org)
+            .write_html_custom(&mut wr, &mut handler)
+            .unwrap();
+        String::from_utf8(wr).unwrap()
     }
 
-    let new_title_inner = elements::Title {
-        level: new_level as usize,
-        ..title.to_owned()
-    };
-    let new_title = Element::Title(new_title_inner);
-    handler.start(writer, &new_title).unwrap()
+    fn to_html(org: &str, firn_properties: bool, drawers: &[&str]) -> String {
+        let handler = MyHtmlHandler {
+            properties: if firn_properties {
+                Some(PropertiesConfig {
+                    drawers: drawers.iter().map(|d| d.to_string()).collect(),
+                    ..PropertiesConfig::default()
+                })
+            } else {
+                None
+            },
+            ..MyHtmlHandler::default()
+        };
+        write_html(org, handler)
+    }
+
+    #[test]
+    fn test_properties() {
+        let org = "* Recipe\n:PROPERTIES:\n:ID: abc-123\n:SERVES: 4 & more\n:END:\n:NOTES:\nSecret note.\n:END:\nBody.\n";
+
+        let html = to_html(org, true, &[]);
+        assert!(html.contains("</h1><dl class=\"firn-properties\"><dt class=\"firn-property-key\">SERVES</dt><dd class=\"firn-property-value\">4 &amp; more</dd></dl>"));
+        assert!(!html.contains("abc-123"));
+        assert!(!html.contains("Secret note."));
+        assert!(html.contains("Body."));
+
+        let html = to_html(org, true, &["notes"]);
+        assert!(
+            html.contains("<div class=\"firn-drawer firn-drawer-notes\"><p>Secret note.</p></div>")
+        );
+
+        // without #+firn_properties, drawers render as they always have.
+        let html = to_html(org, false, &[]);
+        assert!(!html.contains("firn-properties"));
+        assert!(html.contains("Secret note."));
+    }
+
+    #[test]
+    fn test_fold() {
+        let org = "* One\n** Two\n*** Three\n* Hidden\n:PROPERTIES:\n:VISIBILITY: folded\n:END:\n* Everything\n:PROPERTIES:\n:VISIBILITY: all\n:END:\n** Deep\n";
+        let handler = MyHtmlHandler {
+            fold_open_depth: Some(2),
+            ..MyHtmlHandler::default()
+        };
+        let html = write_html(org, handler);
+        let opens: Vec<&str> = html
+            .split("<details class=\"firn-fold\"")
+            .skip(1)
+            .map(|d| {
+                if d.starts_with(" open>") {
+                    "open"
+                } else {
+                    "closed"
+                }
+            })
+            .collect();
+        // One, Two, Three, Hidden, Everything, Deep
+        assert_eq!(
+            vec!["open", "open", "closed", "closed", "open", "open"],
+            opens
+        );
+        assert!(html.contains("<summary><h1 id=\"One\">One</h1></summary><details"));
+        assert_eq!(6, html.matches("</details>").count());
+        assert!(html.ends_with("</details></details></div>"));
+    }
 }
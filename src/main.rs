@@ -1,10 +1,25 @@
+pub mod agenda;
+pub mod archive;
+pub mod calendar;
 pub mod config;
+pub mod crawlers;
 pub mod errors;
+pub mod export;
+pub mod feeds;
+pub mod footnotes;
 pub mod front_matter;
+pub mod gemini;
+pub mod graph;
+pub mod highlight;
 pub mod html;
 pub mod new_site;
 pub mod org;
+pub mod pagination;
+pub mod query;
+pub mod search;
+pub mod series;
 pub mod serve;
+pub mod sitemap_tree;
 pub mod templates;
 pub mod user_config;
 pub mod util;
@@ -13,7 +28,7 @@ use std::env;
 use std::path::PathBuf;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 /// Org Mode static site generator
 #[derive(Parser)]
@@ -39,6 +54,9 @@ enum Command {
     Build {
         /// Directory containing files to be built, defaults to cwd
         path: Option<PathBuf>,
+
+        #[clap(flatten)]
+        publish: Publish,
     },
 
     /// Run a development server for processed org files
@@ -49,9 +67,70 @@ enum Command {
 
         /// Directory containing files to be built and served, defaults to cwd
         path: Option<PathBuf>,
+
+        #[clap(flatten)]
+        publish: Publish,
+    },
+
+    /// Query the links, tags and headlines of a site without building it
+    Query {
+        #[clap(subcommand)]
+        query: query::Query,
+
+        /// Print results as json instead of a table
+        #[clap(long, global = true)]
+        json: bool,
+
+        /// Directory containing the site's files, defaults to cwd
+        #[clap(short, long, global = true)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Export a site, or part of it, to another format
+    Export {
+        #[clap(subcommand)]
+        format: export::Export,
+
+        /// Directory containing the site's files, defaults to cwd
+        #[clap(short, long, global = true)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Write stylesheets for a site
+    Css {
+        #[clap(subcommand)]
+        css: highlight::Css,
+
+        /// Directory containing the site's files, defaults to cwd
+        #[clap(short, long, global = true)]
+        dir: Option<PathBuf>,
     },
 }
 
+/// Which unpublished files to build anyway
+#[derive(Args)]
+struct Publish {
+    /// Build `#+firn_draft` files too
+    #[clap(long)]
+    drafts: bool,
+
+    /// Build files with a `#+date_created` in the future too
+    #[clap(long)]
+    future: bool,
+
+    /// Build the site as it would be on a date (ex: 2022-03-01, or "2022-03-01 18:00")
+    #[clap(long, parse(try_from_str = util::parse_now))]
+    now: Option<i64>,
+}
+
+impl Publish {
+    fn apply(&self, config: &mut config::Config) {
+        config.include_drafts = self.drafts;
+        config.include_future = self.future;
+        config.now = self.now;
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
@@ -60,18 +139,37 @@ fn main() -> Result<()> {
             let path = path_or_cwd(path);
             new_site::init(path);
         }
-        Command::Build { path } => {
+        Command::Build { path, publish } => {
             let path = path_or_cwd(path);
             let mut config = unwrap_config(path, cli.verbose);
+            publish.apply(&mut config);
             config.build(true)?;
         }
-        Command::Serve { port, path } => {
+        Command::Serve { port, path, publish } => {
             let path = path_or_cwd(path);
             let mut config = unwrap_config(path, cli.verbose);
+            publish.apply(&mut config);
             config.setup_for_serve(port);
             config.build(true)?;
             serve::start_server(&mut config);
         }
+        Command::Query { query, json, dir } => {
+            let path = path_or_cwd(dir);
+            let mut config = unwrap_config(path, cli.verbose);
+            config.load();
+            query::run(&config, &query, json);
+        }
+        Command::Export { format, dir } => {
+            let path = path_or_cwd(dir);
+            let mut config = unwrap_config(path, cli.verbose);
+            config.load();
+            export::run(&config, &format)?;
+        }
+        Command::Css { css, dir } => {
+            let path = path_or_cwd(dir);
+            let config = unwrap_config(path, cli.verbose);
+            highlight::run(&config, &css)?;
+        }
     }
     Ok(())
 }
@@ -1,3 +1,5 @@
+use crate::highlight;
+
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
@@ -25,6 +27,23 @@ site:
 file:
   table_of_contents: "no"
   todo_keywords: ["TODO", "DONE"]
+  # wrap headlines in <details> so readers can fold them (or per file, with #+firn_fold).
+  # a headline's :VISIBILITY: property ("folded", "children", "content", "all") wins.
+  fold: false
+  # how many levels of headlines start open when folding; 0 folds them all.
+  fold_open_depth: 1
+  # "section" lists footnotes after the file; "sidenotes" puts them where they are
+  # referenced, with tufte-css classes (ex: for the margins). Footnotes labelled
+  # `mn` or `mn-...` become unnumbered margin notes. Per file, use #+firn_footnotes.
+  footnotes: "section"
+  # highlight src blocks with css classes; write a theme's stylesheet to
+  # static/css/syntax.css with `firn css syntax --theme <name>`.
+  src_highlight: true
+  # number every src block's lines (blocks with `-n` are always numbered);
+  # highlight lines with `:hl_lines 2,4-5`.
+  src_line_numbers: false
+  # caption src blocks with their `:file` header argument, or their #+name.
+  src_captions: true
 
 # Tags ---
 
@@ -43,6 +62,146 @@ tags:
 
   # Set `firn` to true if you want to create a [tag].html page for every *firn_tag* front matter.
   firn: true
+
+  # split tag pages into pages of this many items (tags/<tag>/page/2.html etc.); 0 for one page.
+  per_page: 0
+
+# Posts ---
+# Files with `#+firn_type: post` are listed, newest first, as `posts` in every layout.
+# If `layout` exists, they're also written as paginated listing pages:
+# <url>index.html, <url>page/2.html, ...
+
+posts:
+  url: "blog/"
+  layout: "posts"
+  per_page: 10
+
+# Archive ---
+# Every dated file in the sitemap, grouped by the year and month of its `#+date_created`,
+# is available to every layout as `archive` (with counts, for sidebars).
+# If `layout` exists, they're also written as pages: <url><year>/index.html and
+# <url><year>/<month>/index.html.
+
+archive:
+  url: "archive/"
+  layout: "archive"
+
+# Agenda ---
+# Every headline with one of the `file: todo_keywords` (with its priority, tags,
+# SCHEDULED and DEADLINE) is available to every layout as `agenda`.
+# If `layout` exists, it's also written to `path`, grouped by keyword and date.
+
+agenda:
+  path: "agenda.html"
+  layout: "agenda"
+
+# Logbook ---
+# Every layout can call `logbook()` for the page's clocked time, or
+# `logbook(site=true)` for the whole site's. If `layout` exists, the site's
+# logbook is also written to `path`, with its totals per headline and per day.
+
+logbook:
+  path: "logbook.html"
+  layout: "logbook"
+
+# Feeds ---
+# Atom and RSS feeds are built from every file with `#+firn_type: post`.
+
+feeds:
+  enabled: true
+  # the author of the feeds; leave it empty to use the site's title.
+  author: ""
+  # max number of posts in a feed; 0 means no limit.
+  limit: 20
+  # "full" puts the entire post in the feed; "summary" only renders the
+  # headline named in `summary_headline` (posts without it get no content).
+  content: "full"
+  summary_headline: "Summary"
+  atom_path: "feed.xml"
+  rss_path: "rss.xml"
+  # create a feed for every tag, under <tag_feeds_url><tag_name>/feed.xml
+  tag_feeds: true
+  tag_feeds_url: "feeds/"
+
+# Properties ---
+# How drawers are rendered in files with `#+firn_properties`.
+
+properties:
+  # property keys left out of a headline's properties.
+  hidden: ["ID", "CUSTOM_ID", "VISIBILITY"]
+  # drawers (ex: "NOTES") that are rendered; other drawers are hidden.
+  drawers: []
+
+# Calendar ---
+# An iCalendar file of every SCHEDULED and DEADLINE headline, and every active
+# timestamp, in your public files, to subscribe to from a calendar app.
+# `split` can be "file" or "tag", to also write one calendar per file or tag to `split_url`.
+
+calendar:
+  enabled: false
+  path: "calendar.ics"
+  split: "none"
+  split_url: "calendars/"
+
+# Crawlers ---
+
+crawlers:
+  # write a sitemap.xml of every file in the sitemap, and every tag page.
+  sitemap: true
+  # write a robots.txt
+  robots: true
+  # disallow crawling of the site > ignored_directories above.
+  disallow_ignored_directories: true
+  # other paths to disallow, relative to the site root.
+  disallow: []
+
+# Search ---
+# A json index of every public file, used by partials/search.html.
+# Files can opt out with `#+firn_search: false`.
+
+search:
+  enabled: true
+  path: "search.json"
+
+# Graph ---
+# The links between your files, as graph.json (and optionally graphviz's graph.dot).
+# Every page also gets a `local_graph` of the files around it.
+
+graph:
+  json: true
+  dot: false
+  # how many links away from a page its local_graph reaches.
+  local_graph_depth: 1
+
+# Data API ---
+# Writes a json file next to every html file (ex: foo.html -> foo.html.json)
+# with its front matter, html, headlines, links, backlinks, related files and tags.
+# The sitemap is written to `index_path`.
+
+data_api:
+  enabled: false
+  index_path: "index.json"
+
+# Gemini ---
+# Also writes every public file, the tag pages and a sitemap.gmi as gemtext,
+# to _firn/<path>, for serving over gemini.
+
+gemini:
+  enabled: false
+  url: "gemini://localhost"
+  path: "_gemini"
+
+# Book ---
+# `firn export book` stitches files into one html page, for printing.
+# Without --file or --under, it uses the `files` listed here (by title or path),
+# or else every file in the sitemap, ordered by `#+firn_order` and then date.
+# The page is rendered with layouts/<layout>.html, if it exists.
+# `language` is the language `firn export epub` gives its books (ex: "en", "fr").
+
+book:
+  files: []
+  layout: "book"
+  language: "en"
 "#;
 
 const TAG_TEMPLATE: &str = r#"{% import "macros.html" as macros %}
@@ -60,6 +219,7 @@ const TAG_TEMPLATE: &str = r#"{% import "macros.html" as macros %}
           </li>
         {% endif %}
       {% endfor %}
+      {{ macros::pager(page=page, total_pages=total_pages, prev=prev, next=next) }}
 
 
       {# The below code is commented out.
@@ -96,11 +256,126 @@ const PARTIAL_HEAD: &str = r#"<html>
     <link rel="apple-touch-icon" href="/apple-touch-icon.png">
     <script src="{{config.site.url}}/static/js/main.js"></script>
     <link rel="stylesheet" href="{{config.site.url}}/static/css/main.css" type="text/css" media="screen" />
+    <link rel="stylesheet" href="{{config.site.url}}/static/css/syntax.css" type="text/css" media="screen" />
+    {# canonical url, description, open graph, twitter card and json-ld tags; only pages have them. #}
+    {% if seo %}{{ seo_tags() }}{% endif %}
+    {% if config.feeds.enabled %}
+    <link rel="alternate" type="application/atom+xml" title="{{config.site.title}}" href="{{config.site.url}}/{{config.feeds.atom_path}}" />
+    <link rel="alternate" type="application/rss+xml" title="{{config.site.title}}" href="{{config.site.url}}/{{config.feeds.rss_path}}" />
+    {% endif %}
     <style>
     </style>
   </head>
 "#;
 
+const POSTS_HTML: &str = r#"{% import "macros.html" as macros %}
+<html>
+  {% include "partials/head.html" %}
+  <body style="display: flex;">
+    <main style="width: 600px; margin: 0 auto; padding: 32px;">
+      <h1>Posts</h1>
+      {% for post in posts %}
+        <article>
+          <h2><a href="{{post.path}}">{{post.file}}</a></h2>
+          {% if post.front_matter.date_created %}<time>{{post.front_matter.date_created}}</time>{% endif %}
+        </article>
+      {% endfor %}
+      {{ macros::pager(page=page, total_pages=total_pages, prev=prev, next=next) }}
+    </main>
+  </body>
+</html>
+"#;
+
+const ARCHIVE_HTML: &str = r#"<html>
+  {% include "partials/head.html" %}
+  <body style="display: flex;">
+    <main style="width: 600px; margin: 0 auto; padding: 32px;">
+      <h1>{{ title }}</h1>
+      {% for group in groups %}
+        <section>
+          {% if not month %}<h2><a href="{{group.path}}">{{group.title}}</a></h2>{% endif %}
+          <ul>
+            {% for item in group.items %}
+              <li><a href="{{item.path}}">{{item.file}}</a> <time>{{item.front_matter.date_created}}</time></li>
+            {% endfor %}
+          </ul>
+        </section>
+      {% endfor %}
+    </main>
+    <aside style="padding: 32px; width: 300px;">
+      <ul>
+        {% for y in archive %}
+          <li><a href="{{y.path}}">{{y.year}}</a> ({{y.count}})</li>
+        {% endfor %}
+      </ul>
+    </aside>
+  </body>
+</html>
+"#;
+
+const AGENDA_HTML: &str = r#"<html>
+  {% include "partials/head.html" %}
+  <body>
+    <main style="width: 600px; margin: 0 auto; padding: 32px;">
+      <h1>{{ title }}</h1>
+      {% for group in groups %}
+        <section>
+          <h2>{{ group.keyword }}</h2>
+          {% for day in group.dates %}
+            <h3>{% if day.date %}{{ day.date }}{% else %}Unplanned{% endif %}</h3>
+            <ul>
+              {% for item in day.items %}
+                <li>
+                  {% if item.priority %}[#{{ item.priority }}]{% endif %}
+                  <a href="{{ item.path }}">{{ item.headline }}</a> ({{ item.file }})
+                  {% if item.deadline %}<strong>Deadline: {{ item.deadline }}</strong>{% endif %}
+                  {% for tag in item.tags %}<code>{{ tag }}</code> {% endfor %}
+                </li>
+              {% endfor %}
+            </ul>
+          {% endfor %}
+        </section>
+      {% endfor %}
+    </main>
+  </body>
+</html>
+"#;
+
+const LOGBOOK_HTML: &str = r#"<html>
+  {% include "partials/head.html" %}
+  <body>
+    <main style="width: 600px; margin: 0 auto; padding: 32px;">
+      <h1>{{ title }}</h1>
+      <p>{{ logbook.hours }} hours clocked.</p>
+      <h2>Headlines</h2>
+      <ul>
+        {% for h in logbook.headlines %}
+          <li><a href="{{ h.path }}">{% if h.headline %}{{ h.headline }}{% else %}{{ h.file }}{% endif %}</a> ({{ h.minutes }} min)</li>
+        {% endfor %}
+      </ul>
+      <h2>Days</h2>
+      <ul>
+        {% for day in logbook.days %}
+          <li><time>{{ day.date }}</time>: {{ day.minutes }} min</li>
+        {% endfor %}
+      </ul>
+    </main>
+  </body>
+</html>
+"#;
+
+const BOOK_HTML: &str = r#"<html>
+  {% include "partials/head.html" %}
+  <body>
+    <main>
+      <h1>{{ title }}</h1>
+      <nav class="firn-book-toc">{{ toc | safe }}</nav>
+      {{ content | safe }}
+    </main>
+  </body>
+</html>
+"#;
+
 const PARTIAL_RECENT: &str = r#"{# NOTE! This partial will fail to render if files are missing
 their #+date_created and #+date_updated frontmatter. #}
 {% if sitemap | length > 0 %}
@@ -144,6 +419,40 @@ const MACROS: &str = r#" {# the link_list macro is used to render a generic list
   {% endif %}
 {% endmacro input %}
 
+{# the pager macro links to the previous and next pages of a paginated listing (posts, tag pages). #}
+{% macro pager(page, total_pages, prev, next) %}
+  {% if total_pages > 1 %}
+    <nav>
+      {% if prev %}<a href="{{prev}}">Previous</a>{% endif %}
+      Page {{page}} of {{total_pages}}
+      {% if next %}<a href="{{next}}">Next</a>{% endif %}
+    </nav>
+  {% endif %}
+{% endmacro pager %}
+
+{# the page_nav macro links a page to the ones before and after it, in its series or among the posts. #}
+{% macro page_nav(prev, next, series) %}
+  {% if series %}<p>Part {{series.index + 1}} of {{series.items | length}} in {{series.name}}.</p>{% endif %}
+  {% if prev or next %}
+    <nav>
+      {% if prev %}<a href="{{prev.path}}">← {{prev.file}}</a>{% endif %}
+      {% if next %}<a href="{{next.path}}">{{next.file}} →</a>{% endif %}
+    </nav>
+  {% endif %}
+{% endmacro page_nav %}
+
+{# the nav_tree macro lists the sitemap_tree, with each file's children nested under it (see `#+firn_under`). #}
+{% macro nav_tree(nodes) %}
+  <ul>
+    {% for node in nodes %}
+      <li>
+        <a href="{{node.path}}">{{node.file}}</a>
+        {% if node.children | length > 0 %}{{ self::nav_tree(nodes=node.children) }}{% endif %}
+      </li>
+    {% endfor %}
+  </ul>
+{% endmacro nav_tree %}
+
 "#;
 
 const DEFAULT_HTML: &str = r#"{% import "macros.html" as macros %}
@@ -151,23 +460,92 @@ const DEFAULT_HTML: &str = r#"{% import "macros.html" as macros %}
   {% include "partials/head.html" %}
   <body style="display: flex;">
     <main style="width: 600px; margin: 0 auto; padding: 32px;">
+      {% if draft %}<p class="firn-draft"><strong>Draft:</strong> this page isn't published yet.</p>{% endif %}
       {{render()}}
+      {{macros::page_nav(prev=prev, next=next, series=series)}}
     </main>
 
     <aside style="padding: 32px; width: 300px;">
+      {% include "partials/search.html" %}
      <section>{{toc()}}</section>
       {{macros::link_list(title="Backlinks", list_items=backlinks)}}
       {{macros::link_list(title="Related", list_items=related)}}
-      {{macros::link_list(title="Sitemap", list_items=sitemap)}}
+      {% if sitemap_tree | length > 0 %}
+        <section>
+          <details open>
+            <summary>Sitemap</summary>
+            {{macros::nav_tree(nodes=sitemap_tree)}}
+          </details>
+        </section>
+      {% endif %}
       {{macros::link_list(title="Tags", list_items=tags)}}
     </aside>
   </body>
 </html>
 "#;
 
+const PARTIAL_SEARCH: &str = r#"{% if config.search.enabled %}
+<section class="firn-search">
+  <input type="search" placeholder="Search..." id="firn-search-input"
+         data-index="{{config.site.url}}/{{config.search.path}}" />
+  <ul id="firn-search-results"></ul>
+</section>
+<script src="{{config.site.url}}/static/js/search.js"></script>
+{% endif %}
+"#;
+
 const DEFAULT_JS: &str = r#"
 "#;
 
+const SEARCH_JS: &str = r##"// A tiny client side search over the index Firn writes to search.json.
+(function () {
+  var input = document.getElementById("firn-search-input");
+  var results = document.getElementById("firn-search-results");
+  if (!input || !results) return;
+  var index = null;
+
+  function load() {
+    if (index) return Promise.resolve(index);
+    return fetch(input.dataset.index)
+      .then(function (res) { return res.json(); })
+      .then(function (json) { index = json; return index; });
+  }
+
+  function search(query) {
+    var terms = query.toLowerCase().split(/\s+/).filter(Boolean);
+    var hits = [];
+    index.forEach(function (doc) {
+      doc.sections.forEach(function (section) {
+        var haystack = [doc.title, doc.tags.join(" "), section.headline || "", section.text]
+          .join(" ").toLowerCase();
+        if (terms.every(function (t) { return haystack.indexOf(t) !== -1; })) {
+          var url = section.headline ? doc.url + "#" + encodeURIComponent(section.headline) : doc.url;
+          var label = section.headline ? doc.title + " / " + section.headline : doc.title;
+          hits.push({ url: url, label: label });
+        }
+      });
+    });
+    return hits.slice(0, 20);
+  }
+
+  input.addEventListener("input", function () {
+    var query = input.value.trim();
+    if (query.length < 2) { results.innerHTML = ""; return; }
+    load().then(function () {
+      results.innerHTML = "";
+      search(query).forEach(function (hit) {
+        var li = document.createElement("li");
+        var a = document.createElement("a");
+        a.href = hit.url;
+        a.textContent = hit.label;
+        li.appendChild(a);
+        results.appendChild(li);
+      });
+    });
+  });
+})();
+"##;
+
 const DEFAULT_SCSS: &str = r#"body{
   color: #333;
   background: #efefef;
@@ -191,14 +569,25 @@ pub fn init(cwd: PathBuf) {
     } else {
         let dirs = vec!["layouts/", "layouts/partials", "sass", "static/css", "static/js", "_site"];
 
+        // regenerate it in another theme with `firn css syntax --theme <name>`.
+        let syntax_css = highlight::theme_css("InspiredGitHub").expect("a default syntect theme");
+
         let mut files = HashMap::new();
         files.insert(String::from("layouts/partials/head.html"), PARTIAL_HEAD);
         files.insert(String::from("layouts/macros.html"), MACROS);
         files.insert(String::from("layouts/partials/recent.html"), PARTIAL_RECENT);
+        files.insert(String::from("layouts/partials/search.html"), PARTIAL_SEARCH);
         files.insert(String::from("static/js/main.js"), DEFAULT_JS);
+        files.insert(String::from("static/js/search.js"), SEARCH_JS);
         files.insert(String::from("sass/main.scss"), DEFAULT_SCSS);
+        files.insert(String::from("static/css/syntax.css"), &syntax_css);
         files.insert(String::from("layouts/default.html"), DEFAULT_HTML);
         files.insert(String::from("layouts/[tag].html"), TAG_TEMPLATE);
+        files.insert(String::from("layouts/book.html"), BOOK_HTML);
+        files.insert(String::from("layouts/posts.html"), POSTS_HTML);
+        files.insert(String::from("layouts/archive.html"), ARCHIVE_HTML);
+        files.insert(String::from("layouts/agenda.html"), AGENDA_HTML);
+        files.insert(String::from("layouts/logbook.html"), LOGBOOK_HTML);
         files.insert(String::from("config.yaml"), CONFIG_YAML);
 
         // Map over the above strings, turn them into paths, and create them.
@@ -1,16 +1,14 @@
+use crate::agenda::AgendaItem;
 use crate::config::Config;
 use crate::errors::{FirnError, FirnErrorType};
 use crate::front_matter::{self, FrontMatter};
-use crate::{templates, util};
+use crate::{footnotes, series, sitemap_tree, templates, util};
 
-use chrono::{Duration, NaiveTime};
-use orgize::elements::Clock;
-use orgize::{elements, Element, Event, Org};
+use orgize::{elements, Element, Event, Org, ParseConfig};
 use serde::Serialize;
 use slugify::slugify;
 use std::fs;
 use std::path::{PathBuf, Path};
-use tera::Context;
 
 #[derive(Debug, PartialEq, Eq, Serialize, Clone)]
 pub enum OrgTagType {
@@ -28,7 +26,8 @@ pub enum OrgMetadataType<'a> {
     Clock(elements::Clock<'a>),
     Link(elements::Link<'a>),
     Tag(String, OrgTagType),
-    Sitemap(FrontMatter),
+    Sitemap(Box<FrontMatter>),
+    Agenda(AgendaItem),
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -93,6 +92,16 @@ impl<'a> OrgMetadata<'a> {
     }
 }
 
+/// CollectedData is what OrgFile::collect_data finds in a file:
+/// (links, clocks, tags, agenda, attachments).
+type CollectedData<'a> = (
+    Vec<OrgMetadata<'a>>,
+    Vec<OrgMetadata<'a>>,
+    Vec<OrgMetadata<'a>>,
+    Vec<OrgMetadata<'a>>,
+    Vec<String>,
+);
+
 // OrgFile
 //
 
@@ -120,8 +129,8 @@ pub struct OrgFile<'a> {
     pub logbook: Vec<OrgMetadata<'a>>,
     pub sitemap_data: OrgMetadata<'a>,
     pub tags: Vec<OrgMetadata<'a>>,
+    pub agenda: Vec<OrgMetadata<'a>>,
     pub attachments: Vec<String>,
-    pub posts: Vec<OrgFile<'a>>,
 }
 
 impl<'a> OrgFile<'a> {
@@ -139,19 +148,28 @@ impl<'a> OrgFile<'a> {
             .into_os_string()
             .into_string()
             .expect("Failed to convert web_path to string");
-        let full_url = cfg.base_url.clone().build(web_path_str, file_path.clone());
+        // web_path is already relative to the site root, so we don't resolve
+        // it against the file's parent dirs like we do for links.
+        let full_url = format!("{}/{}", cfg.base_url.base_url, web_path_str);
         let out_path = PathBuf::from(&cfg.dir_site_out).join(&web_path);
-        let parsed = Org::parse_string(read_file);
+        // parse with the user's todo keywords, so headlines (and the agenda) know them.
+        // footnote definitions are left out, as orgize can't validate them (see footnotes.rs).
+        let parsed = Org::parse_string_custom(
+            footnotes::split_definitions(&read_file).0,
+            &ParseConfig {
+                todo_keywords: (cfg.user_config.file.todo_keywords.clone(), vec![]),
+            },
+        );
         let front_matter = FrontMatter::new(&parsed);
         let sitemap_data = OrgMetadata::new(
-            OrgMetadataType::Sitemap(front_matter.clone()),
+            OrgMetadataType::Sitemap(Box::new(front_matter.clone())),
             None,
             &web_path,
             &file_path,
             &front_matter,
         );
 
-        let (links, logbook, tags, attachments) =
+        let (links, logbook, tags, agenda, attachments) =
             OrgFile::collect_data(&parsed, &web_path, &file_path, front_matter.clone());
 
         OrgFile {
@@ -166,8 +184,8 @@ impl<'a> OrgFile<'a> {
             links,
             logbook,
             sitemap_data,
-            posts: Vec::new(),
             tags,
+            agenda,
         }
     }
 
@@ -178,15 +196,11 @@ impl<'a> OrgFile<'a> {
         web_path: &Path,
         file_path: &Path,
         front_matter: front_matter::FrontMatter,
-    ) -> (
-        Vec<OrgMetadata<'a>>,
-        Vec<OrgMetadata<'a>>,
-        Vec<OrgMetadata<'a>>,
-        Vec<String>,
-    ) {
+    ) -> CollectedData<'a> {
         let mut links: Vec<OrgMetadata> = Vec::new();
         let mut clocks: Vec<OrgMetadata> = Vec::new();
         let mut tags: Vec<OrgMetadata> = Vec::new();
+        let mut agenda: Vec<OrgMetadata> = Vec::new();
         let mut attachments: Vec<String> = Vec::new();
         let mut most_recent_title: Vec<elements::Title> = Vec::new();
         let make_metadata = |metadata_type, title: Option<&elements::Title>| {
@@ -202,20 +216,24 @@ impl<'a> OrgFile<'a> {
                     Event::Start(element) => match element {
                         Element::Title(hl) => {
                             most_recent_title = vec![hl.clone()];
+                            if let Some(item) = AgendaItem::from_title(hl) {
+                                agenda.push(make_metadata(
+                                    OrgMetadataType::Agenda(item),
+                                    most_recent_title.first(),
+                                ))
+                            }
                             for tag in &hl.tags {
                                 tags.push(make_metadata(
                                     OrgMetadataType::Tag(tag.to_string(), OrgTagType::OrgTag),
-                                    most_recent_title.get(0),
+                                    most_recent_title.first(),
                                 ))
                             }
                         }
-                        Element::Clock(ts) => {
-                            if ts.is_closed() {
-                                clocks.push(make_metadata(
-                                    OrgMetadataType::Clock(ts.clone()),
-                                    most_recent_title.get(0),
-                                ))
-                            }
+                        Element::Clock(ts) if ts.is_closed() => {
+                            clocks.push(make_metadata(
+                                OrgMetadataType::Clock(ts.clone()),
+                                most_recent_title.first(),
+                            ))
                         }
                         Element::Link(l) => {
                             // if attachments, push to attachment vec.
@@ -225,7 +243,7 @@ impl<'a> OrgFile<'a> {
 
                             links.push(make_metadata(
                                 OrgMetadataType::Link(l.clone()),
-                                most_recent_title.get(0),
+                                most_recent_title.first(),
                             ));
                         }
                         _ => {}
@@ -234,7 +252,7 @@ impl<'a> OrgFile<'a> {
                 }
             }
         }
-        (links, clocks, tags, attachments)
+        (links, clocks, tags, agenda, attachments)
     }
 
     pub fn is_in_private_folder(&self, ignored_dirs: &[String], dir_source: &Path) -> bool {
@@ -276,38 +294,25 @@ impl<'a> OrgFile<'a> {
             ));
         }
 
+        // like `file: footnotes` in config.yaml, which is checked by UserConfig::validate.
+        if let Some(mode) = &self.front_matter.firn_footnotes {
+            if !footnotes::MODES.contains(&&mode[..]) {
+                return Err(FirnError::new(
+                    &format!(
+                        "{} has `#+firn_footnotes: {}`, which must be 'section' or 'sidenotes'",
+                        self.file_path.display(),
+                        mode
+                    ),
+                    FirnErrorType::UnknownFootnotesMode,
+                ));
+            }
+        }
+
         Ok(true)
     }
 
     fn get_logbook_sum(&self) -> chrono::Duration {
-        let mut accumulator = Duration::zero();
-        for log in &self.logbook {
-            if let OrgMetadataType::Clock(clock) = &log.entity {
-                match clock {
-                    Clock::Closed {
-                        start,
-                        end,
-                        repeater: _,
-                        delay: _,
-                        duration: _,
-                        post_blank: _,
-                    } => {
-                        let start: NaiveTime = start.into();
-                        let end: NaiveTime = end.into();
-                        let diff = end - start;
-                        accumulator = accumulator + diff;
-                    }
-                    // we don't handle clocks running
-                    Clock::Running {
-                        start: _,
-                        repeater: _,
-                        delay: _,
-                        post_blank: _,
-                    } => (),
-                }
-            }
-        }
-        accumulator
+        templates::logbook::total(&self.logbook)
     }
 
     /// get_related_files
@@ -315,7 +320,7 @@ impl<'a> OrgFile<'a> {
     /// then loops over the firn_tags/roam_tags for the current file on self.
     /// if the global tag matches one of the firn tags and it's not the current file
     /// then we keep that as a "related link".
-    fn get_related_files(&self, cfg: &Config) -> Vec<templates::links::LinkData> {
+    pub fn get_related_files(&self, cfg: &Config) -> Vec<templates::links::LinkData> {
         let mut out: Vec<_> = Vec::new();
 
         for g_tag in &cfg.global_tags {
@@ -354,14 +359,18 @@ impl<'a> OrgFile<'a> {
 
     /// get_backlinks iterates over every global link
     /// and returns a list of links that links to the current file.
-    fn get_backlinks(&self, cfg: &Config) -> Vec<templates::links::LinkData> {
+    pub fn get_backlinks(&self, cfg: &Config) -> Vec<templates::links::LinkData> {
         let mut out: Vec<_> = Vec::new();
         for g_link in &cfg.global_links {
             // if the weblink matches self's web_path it's a match.
             if let OrgMetadataType::Link(link) = &g_link.entity {
-                let new_link_path = link.path.to_owned().to_string();
-                let web_link =
-                    util::transform_org_link_to_html(cfg.base_url.clone(), new_link_path, self.file_path.clone());
+                let new_link_path = link.path.to_string();
+                // links are relative to the file they were written in.
+                let web_link = util::transform_org_link_to_html(
+                    cfg.base_url.clone(),
+                    new_link_path,
+                    g_link.originating_file_path.clone(),
+                );
 
                 let backlink_item_url = format!(
                     "{}/{}",
@@ -385,26 +394,76 @@ impl<'a> OrgFile<'a> {
         out
     }
 
-    /// Sets up our templates with all the values they might need
-    fn setup_tera_ctx(&self, ctx: &mut tera::Context, cfg: &Config) {
+    /// get_outgoing_links returns every link in the current file,
+    /// with links to other org files resolved to their urls.
+    pub fn get_outgoing_links(&self, cfg: &Config) -> Vec<templates::links::LinkData> {
+        let mut out: Vec<_> = Vec::new();
+        for l in &self.links {
+            if let OrgMetadataType::Link(link) = &l.entity {
+                let web_link = util::transform_org_link_to_html(
+                    cfg.base_url.clone(),
+                    link.path.to_string(),
+                    self.file_path.clone(),
+                );
+                let new_link = templates::links::LinkData::new(
+                    web_link,
+                    link.desc.as_ref().unwrap_or(&link.path).to_string(),
+                    templates::links::LinkMeta::Outgoing,
+                    None,
+                );
+                if !out.contains(&new_link) {
+                    out.push(new_link);
+                }
+            }
+        }
+        out
+    }
+
+    /// write_page_data writes the data api json for the current file
+    /// next to its html, ie: foo.html -> foo.html.json
+    fn write_page_data(&self, cfg: &Config) {
+        let page_data = templates::data::PageData::new(self, cfg);
+        let out_path = PathBuf::from(format!("{}.json", self.out_path.display()));
+        let json = serde_json::to_string(&page_data).expect("Failed to serialize page data.");
+        fs::write(out_path, json).expect("Failed to write page data.");
+    }
+
+    /// Sets up our templates with all the values they might need, on top of
+    /// the site's (see templates::tera::site_context).
+    fn setup_tera_ctx(&self, ctx: &mut tera::Context, cfg: &Config, seo: &templates::Seo) {
         let logbook_sum = self.get_logbook_sum();
         ctx.insert("backlinks", &self.get_backlinks(cfg));
         ctx.insert("title", &self.front_matter.get_title());
         ctx.insert("frontmatter", &self.front_matter);
+        ctx.insert("draft", &self.front_matter.firn_draft);
         ctx.insert("related", &self.get_related_files(cfg));
         ctx.insert("logbook", &logbook_sum.num_hours());
-        ctx.insert("sitemap", &cfg.sitemap);
-        ctx.insert("config", &cfg.user_config);
-        ctx.insert("tags", &cfg.tags_list);
+        let (siblings, children) =
+            sitemap_tree::siblings_and_children(&cfg.sitemap_tree, &self.full_url)
+                .unwrap_or_default();
+        ctx.insert("sitemap_siblings", &siblings);
+        ctx.insert("sitemap_children", &children);
+        let (series, prev, next) = series::neighbours(cfg, self);
+        ctx.insert("series", &series);
+        ctx.insert("prev", &prev);
+        ctx.insert("next", &next);
+        ctx.insert("seo", seo);
+        ctx.insert(
+            "local_graph",
+            &cfg.graph
+                .neighbourhood(&self.full_url, cfg.user_config.graph.local_graph_depth),
+        );
     }
 
     /// render spits out html to disk.
     pub fn render(&self, cfg: &Config) -> Result<(), FirnError> {
         self.valid_for_rendering()?;
-        let tera = templates::tera::setup(cfg, self);
+        // the seo values are both in the context and behind `seo_tags()`.
+        let seo = templates::Seo::new(self, cfg);
         let template_name = util::get_template(&cfg.tera, &self.front_matter.get_layout())?;
-        let mut ctx = Context::new();
-        self.setup_tera_ctx(&mut ctx, cfg);
+        let mut ctx = cfg.site_context.clone();
+        self.setup_tera_ctx(&mut ctx, cfg, &seo);
+        let tera = templates::tera::setup(cfg, self, seo);
 
         if !self.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source) {
             let tera_output = tera
@@ -414,6 +473,9 @@ impl<'a> OrgFile<'a> {
             let parent = self.out_path.parent().expect("File had no parent");
             fs::create_dir_all(parent).expect("Failed to create file out_path.");
             fs::write(&self.out_path, tera_output).expect("Failed to write file.");
+            if cfg.user_config.data_api.enabled {
+                self.write_page_data(cfg);
+            }
         }
         Ok(())
     }
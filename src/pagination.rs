@@ -0,0 +1,147 @@
+use crate::{config::Config, util};
+
+use anyhow::{Context, Result};
+use std::fs;
+
+// -- Pagination -----------------------------------------------------------------
+//
+// Listing pages (the posts listing and tag pages) are split into pages of
+// `per_page` items. The first page keeps the listing's own path (ex:
+// blog/index.html, tags/rust.html) and the rest go under it, in page/<n>.html
+// (ex: blog/page/2.html, tags/rust/page/2.html).
+
+#[derive(Debug)]
+pub struct Page<'i, T> {
+    pub items: &'i [T],
+    /// the web path of this page, relative to the site root.
+    pub web_path: String,
+    pub page: usize,
+    pub total_pages: usize,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+
+impl<'i, T> Page<'i, T> {
+    /// insert adds `page`, `total_pages`, `prev` and `next` (urls) to a tera context.
+    pub fn insert(&self, ctx: &mut tera::Context) {
+        ctx.insert("page", &self.page);
+        ctx.insert("total_pages", &self.total_pages);
+        ctx.insert("prev", &self.prev);
+        ctx.insert("next", &self.next);
+    }
+}
+
+/// page_web_path is where page `n` of a listing goes; `first_page` is the
+/// listing's own path and `dir` is where the rest of its pages go.
+pub fn page_web_path(first_page: &str, dir: &str, n: usize) -> String {
+    if n <= 1 {
+        first_page.to_string()
+    } else {
+        format!("{}page/{}.html", dir, n)
+    }
+}
+
+/// paginate splits `items` into pages of `per_page` (0 puts everything on
+/// one page). There is always at least one page, even if it is empty.
+pub fn paginate<'i, T>(
+    items: &'i [T],
+    per_page: usize,
+    base_url: &str,
+    first_page: &str,
+    dir: &str,
+) -> Vec<Page<'i, T>> {
+    let chunks: Vec<&[T]> = if per_page == 0 || items.is_empty() {
+        vec![items]
+    } else {
+        items.chunks(per_page).collect()
+    };
+    let total_pages = chunks.len();
+    let url = |n: usize| format!("{}/{}", base_url, page_web_path(first_page, dir, n));
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, items)| {
+            let n = i + 1;
+            Page {
+                items,
+                web_path: page_web_path(first_page, dir, n),
+                page: n,
+                total_pages,
+                prev: if n > 1 { Some(url(n - 1)) } else { None },
+                next: if n < total_pages { Some(url(n + 1)) } else { None },
+            }
+        })
+        .collect()
+}
+
+/// build_posts renders the posts listing pages with the posts layout, if the
+/// site has one.
+pub fn build_posts(cfg: &Config) -> Result<()> {
+    let posts_cfg = &cfg.user_config.posts;
+    let layout = format!("{}.html", posts_cfg.layout);
+    if !cfg.tera.get_template_names().any(|t| t == layout) {
+        return Ok(());
+    }
+
+    let first_page = format!("{}index.html", posts_cfg.url);
+    let pages = paginate(
+        &cfg.posts,
+        posts_cfg.per_page,
+        &cfg.user_config.site.url,
+        &first_page,
+        &posts_cfg.url,
+    );
+    for page in pages {
+        let out_path = cfg.dir_site_out.join(&page.web_path);
+        // don't overwrite a page written from an org file (ex: blog/index.org).
+        if cfg.org_files.iter().any(|f| f.out_path == out_path) {
+            println!(
+                "⚠️ Warning: the posts listing was not written to {:?}, as a file is already rendered there.",
+                util::path_to_string(&out_path)
+            );
+            continue;
+        }
+
+        let mut ctx = cfg.site_context.clone();
+        ctx.insert("title", "Posts");
+        ctx.insert("posts", page.items);
+        page.insert(&mut ctx);
+
+        let output = cfg
+            .tera
+            .render(&layout, &ctx)
+            .context("Failed to render posts layout")?;
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create posts directory")?;
+        }
+        fs::write(&out_path, output).context("Failed to write posts page")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        let items = [1, 2, 3, 4, 5];
+        let pages = paginate(&items, 2, "https://x.com", "blog/index.html", "blog/");
+        assert_eq!(3, pages.len());
+        assert_eq!(&[1, 2], pages[0].items);
+        assert_eq!("blog/index.html", pages[0].web_path);
+        assert_eq!(None, pages[0].prev);
+        assert_eq!(Some("https://x.com/blog/page/2.html".to_string()), pages[0].next);
+        assert_eq!("blog/page/3.html", pages[2].web_path);
+        assert_eq!(&[5], pages[2].items);
+        assert_eq!(Some("https://x.com/blog/page/2.html".to_string()), pages[2].prev);
+        assert_eq!(None, pages[2].next);
+        assert_eq!(3, pages[2].total_pages);
+
+        // 0 per page, or no items, is a single page.
+        assert_eq!(1, paginate(&items, 0, "", "tags/rust.html", "tags/rust/").len());
+        let empty: [i32; 0] = [];
+        assert_eq!(1, paginate(&empty, 2, "", "blog/index.html", "blog/").len());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    config::Config,
+    org::{OrgFile, OrgMetadataType},
+    util,
+};
+
+use clap::Subcommand;
+use serde::Serialize;
+
+// -- Query ----------------------------------------------------------------------
+//
+// `firn query` loads a site the same way `firn build` does, but instead of
+// rendering html it answers questions about the collected data, and prints
+// the answer as a table or as json (for scripting against your notes).
+
+#[derive(Subcommand)]
+pub enum Query {
+    /// Files that link to a file (matched by title or by path, ex: blog/post.org)
+    LinksTo { file: String },
+    /// Files and headlines tagged with a tag
+    Tagged { tag: String },
+    /// Public files that no other file links to
+    Orphans,
+    /// Headlines with a todo keyword (ex: TODO)
+    Todo { keyword: String },
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct QueryRow {
+    pub title: String,
+    pub path: String,
+    pub headline: Option<String>,
+    pub url: String,
+}
+
+impl QueryRow {
+    fn new(file: &OrgFile, cfg: &Config, headline: Option<String>) -> QueryRow {
+        QueryRow {
+            title: file.front_matter.get_title().to_string(),
+            path: relative_path(file, cfg),
+            headline,
+            url: file.full_url.clone(),
+        }
+    }
+}
+
+fn relative_path(file: &OrgFile, cfg: &Config) -> String {
+    let path = file.file_path.strip_prefix(&cfg.dir_source).unwrap_or(&file.file_path);
+    util::path_to_string(path)
+}
+
+fn public_files<'c, 'a>(cfg: &'c Config<'a>) -> impl Iterator<Item = &'c OrgFile<'a>> {
+    cfg.org_files
+        .iter()
+        .filter(move |f| !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source))
+}
+
+/// find_file looks a file up by its title (case insensitive), or by its
+/// path relative to the site's directory.
+pub fn find_file<'c, 'a>(cfg: &'c Config<'a>, needle: &str) -> Option<&'c OrgFile<'a>> {
+    let needle_lower = needle.to_lowercase();
+    public_files(cfg).find(|f| {
+        f.front_matter.get_title().to_lowercase() == needle_lower
+            || relative_path(f, cfg) == needle
+    })
+}
+
+fn links_to(cfg: &Config, needle: &str) -> Vec<QueryRow> {
+    let file = match find_file(cfg, needle) {
+        Some(file) => file,
+        None => {
+            println!("Error: no public file found with the title or path {:?}", needle);
+            util::exit();
+        }
+    };
+    let backlinks = file.get_backlinks(cfg);
+    public_files(cfg)
+        .filter(|f| backlinks.iter().any(|bl| bl.path == f.full_url))
+        .map(|f| QueryRow::new(f, cfg, None))
+        .collect()
+}
+
+fn tagged(cfg: &Config, tag: &str) -> Vec<QueryRow> {
+    let mut out = Vec::new();
+    for g_tag in &cfg.global_tags {
+        if let OrgMetadataType::Tag(tag_name, _) = &g_tag.entity {
+            if !tag_name.eq_ignore_ascii_case(tag) {
+                continue;
+            }
+            if let Some(f) = cfg
+                .org_files
+                .iter()
+                .find(|f| f.file_path == g_tag.originating_file_path)
+            {
+                let row = QueryRow::new(f, cfg, g_tag.originating_headline.clone());
+                if !out.contains(&row) {
+                    out.push(row);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn orphans(cfg: &Config) -> Vec<QueryRow> {
+    public_files(cfg)
+        .filter(|f| f.front_matter.title.is_some() && f.get_backlinks(cfg).is_empty())
+        .map(|f| QueryRow::new(f, cfg, None))
+        .collect()
+}
+
+fn todo(cfg: &Config, keyword: &str) -> Vec<QueryRow> {
+    let mut out = Vec::new();
+    for f in public_files(cfg) {
+        for headline in f.parsed.headlines() {
+            let title = headline.title(&f.parsed);
+            if title.keyword.as_deref() == Some(keyword) {
+                out.push(QueryRow::new(f, cfg, Some(title.raw.to_string())));
+            }
+        }
+    }
+    out
+}
+
+fn print_table(rows: &[QueryRow]) {
+    let headers = ["TITLE", "HEADLINE", "PATH"];
+    let cells: Vec<[&str; 3]> = rows
+        .iter()
+        .map(|r| [&r.title[..], r.headline.as_deref().unwrap_or("-"), &r.path[..]])
+        .collect();
+    let mut widths = headers.map(|h| h.len());
+    for row in &cells {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let print_row = |row: [&str; 3]| {
+        println!(
+            "{:w0$}  {:w1$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            w0 = widths[0],
+            w1 = widths[1]
+        )
+    };
+    print_row(headers);
+    for row in cells {
+        print_row(row);
+    }
+}
+
+/// run answers a query against an already loaded config (see Config::load).
+pub fn run(cfg: &Config, query: &Query, json: bool) {
+    let rows = match query {
+        Query::LinksTo { file } => links_to(cfg, file),
+        Query::Tagged { tag } => tagged(cfg, tag),
+        Query::Orphans => orphans(cfg),
+        Query::Todo { keyword } => todo(cfg, keyword),
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&rows).expect("Failed to serialize query results.")
+        );
+    } else {
+        print_table(&rows);
+    }
+}
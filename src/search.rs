@@ -0,0 +1,152 @@
+use crate::{config::Config, org::OrgFile, org::OrgMetadataType};
+
+use anyhow::{Context, Result};
+use orgize::{Element, Event, Org};
+use serde::Serialize;
+use std::fs;
+
+// -- Search ---------------------------------------------------------------------
+//
+// A compact json index of every public file, for client side search.
+// Each file's plain text is split per headline, so results can deep link
+// to the headline's anchor (which is the headline's raw text; see html.rs).
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct SearchSection {
+    pub headline: Option<String>,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchDocument {
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub sections: Vec<SearchSection>,
+}
+
+impl SearchDocument {
+    pub fn new(file: &OrgFile) -> SearchDocument {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in &file.tags {
+            if let OrgMetadataType::Tag(tag_name, _) = &tag.entity {
+                let tag_name = tag_name.to_lowercase();
+                if !tags.contains(&tag_name) {
+                    tags.push(tag_name);
+                }
+            }
+        }
+
+        SearchDocument {
+            title: file.front_matter.get_title().to_string(),
+            url: file.full_url.clone(),
+            tags,
+            sections: collect_sections(&file.parsed),
+        }
+    }
+}
+
+/// collect_sections walks the parsed org content, the same way
+/// OrgFile::collect_data does, and gathers plain text under each headline.
+pub fn collect_sections(parsed: &Org) -> Vec<SearchSection> {
+    // text before the first headline lives in a section without a headline.
+    let mut sections = vec![SearchSection::default()];
+    let mut in_title = false;
+
+    for event in parsed.iter() {
+        let current = sections.last_mut().expect("sections is never empty");
+        match event {
+            Event::Start(element) => match element {
+                Element::Title(title) => {
+                    in_title = true;
+                    sections.push(SearchSection {
+                        headline: Some(title.raw.to_string()),
+                        text: String::new(),
+                    });
+                }
+                // the title's text is already captured from title.raw.
+                _ if in_title => {}
+                Element::Text { value } | Element::Code { value } | Element::Verbatim { value } => {
+                    current.text.push_str(value)
+                }
+                Element::Link(link) => current
+                    .text
+                    .push_str(link.desc.as_ref().unwrap_or(&link.path)),
+                _ => {}
+            },
+            Event::End(element) => match element {
+                Element::Title(_) => in_title = false,
+                // keep words in neighbouring blocks from running together.
+                Element::Paragraph { .. }
+                | Element::ListItem(_)
+                | Element::TableCell(_)
+                | Element::Section => current.text.push(' '),
+                _ => {}
+            },
+        }
+    }
+
+    for section in sections.iter_mut() {
+        section.text = section.text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    sections.retain(|s| s.headline.is_some() || !s.text.is_empty());
+    sections
+}
+
+/// build writes the search index for every file that is public,
+/// in the sitemap, and hasn't opted out with `#+firn_search: false`.
+pub fn build(cfg: &Config) -> Result<()> {
+    let search_cfg = &cfg.user_config.search;
+    if !search_cfg.enabled {
+        return Ok(());
+    }
+
+    let documents: Vec<SearchDocument> = cfg
+        .org_files
+        .iter()
+        .filter(|f| {
+            f.front_matter.can_be_put_into_sitemap()
+                && f.front_matter.firn_search
+                && !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+        })
+        .map(SearchDocument::new)
+        .collect();
+
+    let out_path = cfg.dir_site_out.join(&search_cfg.path);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create search index directory")?;
+    }
+    let index = serde_json::to_string(&documents).context("Failed to serialize search index")?;
+    fs::write(&out_path, index).context("Failed to write search index")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect_sections() {
+        let parsed = Org::parse(
+            "#+title: Test\n\nIntro with a [[https://example.com][link]].\n\n* First :tag:\nSome *bold*\ntext.\n- one\n- two\n** TODO Nested =code=\n",
+        );
+        let sections = collect_sections(&parsed);
+        assert_eq!(
+            sections,
+            vec![
+                SearchSection {
+                    headline: None,
+                    text: "Intro with a link.".to_string()
+                },
+                SearchSection {
+                    headline: Some("First".to_string()),
+                    text: "Some bold text. one two".to_string()
+                },
+                SearchSection {
+                    headline: Some("Nested =code=".to_string()),
+                    text: "".to_string()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    config::Config,
+    org::OrgFile,
+    templates::links::{LinkData, LinkMeta},
+};
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+// -- Series ---------------------------------------------------------------------
+//
+// Files that share a `#+firn_series` are read in order: by `#+firn_series_order`,
+// then oldest first. Every page in a series gets the whole series, and its
+// prev/next within it; posts outside of a series get the posts either side of
+// them by date instead.
+
+/// Series is a page's series, as given to templates.
+#[derive(Debug, Serialize)]
+pub struct Series<'a> {
+    pub name: &'a str,
+    /// where the current page is in `items`, from 0.
+    pub index: usize,
+    pub items: &'a [LinkData],
+}
+
+/// collect groups the public files by their series (lowercased), each in order.
+pub fn collect(cfg: &Config) -> HashMap<String, Vec<LinkData>> {
+    let mut by_series: HashMap<String, Vec<&OrgFile>> = HashMap::new();
+    for f in &cfg.org_files {
+        if let Some(name) = &f.front_matter.firn_series {
+            if f.front_matter.title.is_some()
+                && !f.is_private(&cfg.user_config.site.ignored_directories, &cfg.dir_source)
+            {
+                by_series.entry(name.to_lowercase()).or_default().push(f);
+            }
+        }
+    }
+
+    by_series
+        .into_iter()
+        .map(|(name, mut files)| {
+            // files without a firn_series_order, and then without a date_created, go last.
+            files.sort_by_key(|f| {
+                (
+                    f.front_matter.firn_series_order.is_none(),
+                    f.front_matter.firn_series_order,
+                    f.front_matter.date_created_ts.is_none(),
+                    f.front_matter.date_created_ts,
+                    f.front_matter.get_title().to_string(),
+                )
+            });
+            let items = files
+                .into_iter()
+                .map(|f| {
+                    LinkData::new(
+                        f.full_url.clone(),
+                        f.front_matter.get_title().to_string(),
+                        LinkMeta::Series,
+                        Some(f.front_matter.clone()),
+                    )
+                })
+                .collect();
+            (name, items)
+        })
+        .collect()
+}
+
+/// position finds the page at `path` in `items`, and the items either side of it.
+pub fn position<'a>(
+    items: &'a [LinkData],
+    path: &str,
+) -> Option<(usize, Option<&'a LinkData>, Option<&'a LinkData>)> {
+    let i = items.iter().position(|item| item.path == path)?;
+    let before = if i > 0 { items.get(i - 1) } else { None };
+    Some((i, before, items.get(i + 1)))
+}
+
+/// neighbours returns a page's series (if it is in one), and its prev and next pages.
+pub fn neighbours<'a>(
+    cfg: &'a Config,
+    o: &'a OrgFile,
+) -> (
+    Option<Series<'a>>,
+    Option<&'a LinkData>,
+    Option<&'a LinkData>,
+) {
+    if let Some(name) = &o.front_matter.firn_series {
+        if let Some(items) = cfg.series.get(&name.to_lowercase()) {
+            if let Some((index, prev, next)) = position(items, &o.full_url) {
+                // the series is named as its first file spells it.
+                let name = items[0]
+                    .front_matter
+                    .as_ref()
+                    .and_then(|fm| fm.firn_series.as_deref())
+                    .unwrap_or(name);
+                let series = Series { name, index, items };
+                return (Some(series), prev, next);
+            }
+        }
+    }
+    // posts are newest first, so the previous post comes after this one.
+    match position(&cfg.posts, &o.full_url) {
+        Some((_, newer, older)) => (None, older, newer),
+        None => (None, None, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position() {
+        let items: Vec<LinkData> = ["a", "b", "c"]
+            .iter()
+            .map(|f| LinkData::new(f.to_string(), f.to_string(), LinkMeta::Series, None))
+            .collect();
+        let (i, prev, next) = position(&items, "a").unwrap();
+        assert_eq!((0, None), (i, prev));
+        assert_eq!("b", next.unwrap().file);
+        let (i, prev, next) = position(&items, "c").unwrap();
+        assert_eq!((2, None), (i, next));
+        assert_eq!("b", prev.unwrap().file);
+        assert!(position(&items, "d").is_none());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{
+    errors::{FirnError, FirnErrorType},
+    front_matter::FrontMatter,
+    templates::links::LinkData,
+};
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+// -- Sitemap Tree ---------------------------------------------------------------
+//
+// The sitemap, nested by `#+firn_under`. A file's firn_under names its
+// parents from the top down (ex: `#+firn_under: Research Languages`), so the
+// last one is the file it sits under. Files without one, or whose parent
+// can't be found, sit at the root.
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SitemapNode {
+    pub path: String,
+    pub file: String,
+    pub front_matter: Option<FrontMatter>,
+    pub children: Vec<SitemapNode>,
+}
+
+fn parent_title(entry: &LinkData) -> Option<&String> {
+    entry
+        .front_matter
+        .as_ref()
+        .and_then(|fm| fm.firn_under.as_ref())
+        .and_then(|under| under.last())
+}
+
+/// build_tree nests the (sorted) sitemap by firn_under, and returns a
+/// warning for each unknown parent, and each cycle that had to be broken.
+pub fn build_tree(sitemap: &[LinkData]) -> (Vec<SitemapNode>, Vec<FirnError>) {
+    let mut warnings = Vec::new();
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    for (i, entry) in sitemap.iter().enumerate() {
+        by_title.entry(entry.file.to_lowercase()).or_insert(i);
+    }
+
+    let mut parents: Vec<Option<usize>> = sitemap
+        .iter()
+        .map(|entry| {
+            let parent = parent_title(entry)?;
+            let found = by_title.get(&parent.to_lowercase()).copied();
+            if found.is_none() {
+                warnings.push(FirnError::new(
+                    &format!(
+                        "{:?} is under {:?}, which isn't in the sitemap; it was put at the root.",
+                        entry.file, parent
+                    ),
+                    FirnErrorType::SitemapUnknownParent,
+                ));
+            }
+            found
+        })
+        .collect();
+
+    // a file can't be under itself; the first file found in a cycle goes to the root.
+    for i in 0..sitemap.len() {
+        let mut chain = vec![i];
+        let mut current = parents[i];
+        while let Some(p) = current {
+            if p == i {
+                let names: Vec<&str> = chain.iter().map(|&c| &sitemap[c].file[..]).collect();
+                warnings.push(FirnError::new(
+                    &format!(
+                        "{:?} is under itself ({} > {}); it was put at the root.",
+                        sitemap[i].file,
+                        names.join(" > "),
+                        sitemap[i].file
+                    ),
+                    FirnErrorType::SitemapCycle,
+                ));
+                parents[i] = None;
+                break;
+            }
+            if chain.contains(&p) {
+                // a cycle further up, which will be broken from one of its own files.
+                break;
+            }
+            chain.push(p);
+            current = parents[p];
+        }
+    }
+
+    fn node(i: usize, sitemap: &[LinkData], parents: &[Option<usize>]) -> SitemapNode {
+        SitemapNode {
+            path: sitemap[i].path.clone(),
+            file: sitemap[i].file.clone(),
+            front_matter: sitemap[i].front_matter.clone(),
+            children: (0..sitemap.len())
+                .filter(|&c| parents[c] == Some(i))
+                .map(|c| node(c, sitemap, parents))
+                .collect(),
+        }
+    }
+    let roots = (0..sitemap.len())
+        .filter(|&i| parents[i].is_none())
+        .map(|i| node(i, sitemap, &parents))
+        .collect();
+    (roots, warnings)
+}
+
+/// siblings_and_children finds the node at `path`, and returns the other
+/// nodes under its parent (or at the root), and its own children.
+pub fn siblings_and_children(
+    tree: &[SitemapNode],
+    path: &str,
+) -> Option<(Vec<SitemapNode>, Vec<SitemapNode>)> {
+    if let Some(found) = tree.iter().find(|n| n.path == path) {
+        let siblings = tree.iter().filter(|n| n.path != path).cloned().collect();
+        return Some((siblings, found.children.clone()));
+    }
+    tree.iter()
+        .find_map(|n| siblings_and_children(&n.children, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::templates::links::LinkMeta;
+
+    fn entry(title: &str, under: Option<Vec<&str>>) -> LinkData {
+        let front_matter = FrontMatter {
+            title: Some(title.to_string()),
+            firn_under: under.map(|u| u.iter().map(|s| s.to_string()).collect()),
+            ..FrontMatter::default()
+        };
+        LinkData::new(
+            format!("/{}.html", title.to_lowercase()),
+            title.to_string(),
+            LinkMeta::Sitemap,
+            Some(front_matter),
+        )
+    }
+
+    fn titles(nodes: &[SitemapNode]) -> Vec<&str> {
+        nodes.iter().map(|n| &n.file[..]).collect()
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let sitemap = vec![
+            entry("A", Some(vec!["C"])),
+            entry("B", Some(vec!["Missing"])),
+            entry("Languages", Some(vec!["Research"])),
+            entry("Rust", Some(vec!["Research", "languages"])),
+            entry("C", Some(vec!["A"])),
+            entry("Research", None),
+        ];
+        let (tree, warnings) = build_tree(&sitemap);
+
+        // A <-> C is a cycle, broken at A; B's parent is unknown.
+        assert_eq!(vec!["A", "B", "Research"], titles(&tree));
+        assert_eq!(vec!["C"], titles(&tree[0].children));
+        assert_eq!(vec!["Languages"], titles(&tree[2].children));
+        assert_eq!(vec!["Rust"], titles(&tree[2].children[0].children));
+        let kinds: Vec<_> = warnings.iter().map(|w| w.kind.clone()).collect();
+        assert_eq!(
+            vec![FirnErrorType::SitemapUnknownParent, FirnErrorType::SitemapCycle],
+            kinds
+        );
+
+        let (siblings, children) = siblings_and_children(&tree, "/languages.html").unwrap();
+        assert!(siblings.is_empty());
+        assert_eq!(vec!["Rust"], titles(&children));
+        let (siblings, _) = siblings_and_children(&tree, "/b.html").unwrap();
+        assert_eq!(vec!["A", "Research"], titles(&siblings));
+        assert!(siblings_and_children(&tree, "/nope.html").is_none());
+    }
+}
@@ -1,6 +1,8 @@
 use crate::{
+    config::Config,
     front_matter::FrontMatter,
-    org::{OrgMetadata, OrgTagType, OrgMetadataType},
+    org::{OrgFile, OrgMetadata, OrgTagType, OrgMetadataType},
+    templates::{links::LinkData, Render},
     util,
 };
 /// The data file is mostly for "serializing" larger internal structs that
@@ -26,6 +28,7 @@ impl Backlink {
 /// specifically for enabling the user to render tag data in templates.
 pub struct Tag {
     // pub entity: OrgMetadataType<'a>,
+    pub name: String,
     pub tag_type: String,
     pub title: String,
     pub path: String,
@@ -36,7 +39,7 @@ impl Tag {
     // I guess this could fail if I didn't pass in org metadata of type tag...
     pub fn new(om: OrgMetadata, baseurl: String) -> Tag {
         match om.entity {
-            OrgMetadataType::Tag(_tag_name, tag_type) => match tag_type {
+            OrgMetadataType::Tag(tag_name, tag_type) => match tag_type {
                 OrgTagType::FirnTag => {
                     let path = format!(
                         "{}/{}",
@@ -44,6 +47,7 @@ impl Tag {
                         util::path_to_string(&om.originating_file_web_path)
                     );
                     Tag {
+                        name: tag_name,
                         tag_type: "firn".to_string(),
                         title: om.originating_file,
                         path,
@@ -56,6 +60,7 @@ impl Tag {
                     );
                     let path = format!("{}/{}", baseurl, loc);
                     Tag {
+                        name: tag_name,
                         tag_type: "org".to_string(),
                         title: om
                             .originating_headline
@@ -72,3 +77,62 @@ impl Tag {
         }
     }
 }
+
+#[derive(Debug, PartialEq, Serialize)]
+/// Headline is an entry in the outline of a file.
+pub struct Headline {
+    pub level: usize,
+    pub title: String,
+    pub keyword: Option<String>,
+    pub anchor: String,
+}
+
+#[derive(Debug, Serialize)]
+/// PageData is everything we know about a single file, written to
+/// `<web_path>.json` when the data api is enabled.
+pub struct PageData {
+    pub title: String,
+    pub url: String,
+    pub front_matter: FrontMatter,
+    pub html: String,
+    pub headlines: Vec<Headline>,
+    pub links: Vec<LinkData>,
+    pub backlinks: Vec<LinkData>,
+    pub related: Vec<LinkData>,
+    pub tags: Vec<Tag>,
+}
+
+impl PageData {
+    pub fn new(o: &OrgFile, cfg: &Config) -> PageData {
+        let headlines = o
+            .parsed
+            .headlines()
+            .map(|hl| {
+                let title = hl.title(&o.parsed);
+                Headline {
+                    level: title.level,
+                    title: title.raw.to_string(),
+                    keyword: title.keyword.as_ref().map(|k| k.to_string()),
+                    // the same id that MyHtmlHandler gives the headline.
+                    anchor: title.raw.to_string(),
+                }
+            })
+            .collect();
+
+        PageData {
+            title: o.front_matter.get_title().to_string(),
+            url: o.full_url.clone(),
+            front_matter: o.front_matter.clone(),
+            html: Render::new(o, cfg).render_entire_file(None),
+            headlines,
+            links: o.get_outgoing_links(cfg),
+            backlinks: o.get_backlinks(cfg),
+            related: o.get_related_files(cfg),
+            tags: o
+                .tags
+                .iter()
+                .map(|t| Tag::new(t.to_owned(), cfg.user_config.site.url.to_string()))
+                .collect(),
+        }
+    }
+}
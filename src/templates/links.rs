@@ -6,9 +6,12 @@ use serde::Serialize;
 #[derive(Debug, PartialEq, Serialize)]
 pub enum LinkMeta {
     Backlink,
+    Outgoing,
     RelatedFile,
     Tag { count: usize },
     Sitemap,
+    Post,
+    Series,
 }
 
 #[derive(Debug, PartialEq, Serialize)]
@@ -0,0 +1,222 @@
+use crate::config::Config;
+use crate::org::{OrgMetadata, OrgMetadataType};
+use crate::util::headline_href;
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use orgize::elements::Clock;
+use serde::Serialize;
+use serde_json::value::{to_value, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::sync::Arc;
+use tera::{from_value, Function as TeraFn, Result as TeraResult};
+
+/// HeadlineClock is the time clocked under one headline (or before the first one).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeadlineClock {
+    pub file: String,
+    pub headline: Option<String>,
+    pub path: String,
+    pub minutes: i64,
+}
+
+/// DayClock is the time clocked on one day, for heatmaps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayClock {
+    /// YYYY-MM-DD
+    pub date: String,
+    pub minutes: i64,
+}
+
+/// Logbook totals the closed clocks of a file, or of the whole site.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Logbook {
+    pub minutes: i64,
+    pub hours: f64,
+    /// the most clocked headlines first.
+    pub headlines: Vec<HeadlineClock>,
+    /// every day with time clocked, oldest first.
+    pub days: Vec<DayClock>,
+}
+
+/// clock_span is when a closed clock started and ended.
+pub fn clock_span(clock: &Clock) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    match clock {
+        Clock::Closed { start, end, .. } => Some((start.into(), end.into())),
+        // we don't handle clocks running
+        Clock::Running { .. } => None,
+    }
+}
+
+/// split_by_day splits a span into the minutes it covers on each day, so
+/// clocks running past midnight count towards both days.
+fn split_by_day(start: NaiveDateTime, end: NaiveDateTime) -> Vec<(NaiveDate, i64)> {
+    let mut out = Vec::new();
+    let mut current = start;
+    while current < end {
+        let midnight = (current.date() + Duration::days(1)).and_hms(0, 0, 0);
+        let until = if end < midnight { end } else { midnight };
+        out.push((current.date(), (until - current).num_minutes()));
+        current = until;
+    }
+    out
+}
+
+impl Logbook {
+    /// new totals the clocks in `logbook` (OrgMetadata of OrgMetadataType::Clock).
+    pub fn new(logbook: &[OrgMetadata], base_url: &str) -> Logbook {
+        let mut headlines: Vec<HeadlineClock> = Vec::new();
+        let mut days: BTreeMap<NaiveDate, i64> = BTreeMap::new();
+
+        for log in logbook {
+            let (start, end) = match &log.entity {
+                OrgMetadataType::Clock(clock) => match clock_span(clock) {
+                    Some(span) => span,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let minutes = (end - start).num_minutes().max(0);
+            for (day, day_minutes) in split_by_day(start, end) {
+                *days.entry(day).or_default() += day_minutes;
+            }
+
+            let file_url = format!("{}/{}", base_url, log.get_web_path_as_str());
+            let path = match &log.originating_headline {
+                Some(headline) => headline_href(&file_url, headline),
+                None => file_url,
+            };
+            match headlines.iter_mut().find(|h| h.path == path) {
+                Some(h) => h.minutes += minutes,
+                None => headlines.push(HeadlineClock {
+                    file: log.originating_file.clone(),
+                    headline: log.originating_headline.clone(),
+                    path,
+                    minutes,
+                }),
+            }
+        }
+
+        headlines.sort_by_key(|h| std::cmp::Reverse(h.minutes));
+        let minutes = headlines.iter().map(|h| h.minutes).sum();
+        Logbook {
+            minutes,
+            hours: (minutes as f64 / 60.0 * 100.0).round() / 100.0,
+            headlines,
+            days: days
+                .into_iter()
+                .map(|(day, minutes)| DayClock {
+                    date: day.format("%Y-%m-%d").to_string(),
+                    minutes,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// LogbookFn is the `logbook()` tera function: the current file's logbook,
+/// or, with `logbook(site=true)`, the whole site's.
+pub struct LogbookFn {
+    page: Logbook,
+    /// shared by every page's LogbookFn.
+    site: Arc<Logbook>,
+}
+
+impl LogbookFn {
+    pub fn new(page_logbook: &[OrgMetadata], site: &Arc<Logbook>, base_url: &str) -> LogbookFn {
+        LogbookFn {
+            page: Logbook::new(page_logbook, base_url),
+            site: Arc::clone(site),
+        }
+    }
+}
+
+impl TeraFn for LogbookFn {
+    fn call(&self, args: &HashMap<String, Value>) -> TeraResult<Value> {
+        let site = match args.get("site") {
+            Some(val) => from_value::<bool>(val.clone())?,
+            None => false,
+        };
+        let logbook = if site { self.site.as_ref() } else { &self.page };
+        Ok(to_value(logbook).unwrap())
+    }
+}
+
+/// build renders the site's logbook with the logbook layout, if the site has one.
+pub fn build(cfg: &Config) -> Result<()> {
+    let logbook_cfg = &cfg.user_config.logbook;
+    let layout = format!("{}.html", logbook_cfg.layout);
+    if !cfg.tera.get_template_names().any(|t| t == layout) {
+        return Ok(());
+    }
+
+    let mut ctx = cfg.site_context.clone();
+    ctx.insert("title", "Logbook");
+    ctx.insert("logbook", cfg.logbook.as_ref());
+
+    let output = cfg
+        .tera
+        .render(&layout, &ctx)
+        .context("Failed to render logbook layout")?;
+    let out_path = cfg.dir_site_out.join(&logbook_cfg.path);
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).context("Failed to create logbook directory")?;
+    }
+    fs::write(&out_path, output).context("Failed to write logbook page")
+}
+
+/// total is the time clocked in a file's logbook.
+pub fn total(logbook: &[OrgMetadata]) -> Duration {
+    logbook
+        .iter()
+        .filter_map(|log| match &log.entity {
+            OrgMetadataType::Clock(clock) => clock_span(clock),
+            _ => None,
+        })
+        .fold(Duration::zero(), |acc, (start, end)| acc + (end - start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{front_matter::FrontMatter, org::OrgFile};
+    use orgize::Org;
+    use std::path::Path;
+
+    #[test]
+    fn test_logbook() {
+        let parsed = Org::parse(
+            "* Writing\n:LOGBOOK:\nCLOCK: [2022-01-01 Sat 23:00]--[2022-01-02 Sun 01:30] =>  2:30\nCLOCK: [2022-01-02 Sun 10:00]--[2022-01-02 Sun 10:30] =>  0:30\n:END:\n* Editing\n:LOGBOOK:\nCLOCK: [2022-01-03 Mon 09:00]--[2022-01-03 Mon 09:15] =>  0:15\n:END:\n",
+        );
+        let (_, logbook, _, _, _) = OrgFile::collect_data(
+            &parsed,
+            Path::new("notes.html"),
+            Path::new("notes.org"),
+            FrontMatter {
+                title: Some("Notes".to_string()),
+                ..FrontMatter::default()
+            },
+        );
+        assert_eq!(195, total(&logbook).num_minutes());
+
+        let logbook = Logbook::new(&logbook, "https://x.com");
+        assert_eq!(195, logbook.minutes);
+        assert_eq!(3.25, logbook.hours);
+        assert_eq!(2, logbook.headlines.len());
+        assert_eq!(Some("Writing".to_string()), logbook.headlines[0].headline);
+        assert_eq!(180, logbook.headlines[0].minutes);
+        assert_eq!(
+            "https://x.com/notes.html#Writing",
+            logbook.headlines[0].path
+        );
+        let days: Vec<(&str, i64)> = logbook
+            .days
+            .iter()
+            .map(|d| (&d.date[..], d.minutes))
+            .collect();
+        assert_eq!(
+            vec![("2022-01-01", 60), ("2022-01-02", 120), ("2022-01-03", 15)],
+            days
+        );
+    }
+}
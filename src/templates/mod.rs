@@ -1,11 +1,15 @@
 #[macro_use]
 mod macros;
 
+pub mod logbook;
 mod render;
+mod seo;
 pub mod tera;
 pub mod toc;
 pub mod data;
 pub mod links;
 
+pub use self::logbook::{Logbook, LogbookFn};
 pub use self::render::Render;
+pub use self::seo::{Seo, SeoTags};
 pub use self::toc::Toc;
@@ -1,15 +1,18 @@
 use crate::{
     config::{BaseUrl, Config},
     errors::{FirnError, FirnErrorType},
+    footnotes::{self, Footnotes},
     front_matter,
     html::{self, MyHtmlHandler},
     org::OrgFile,
     user_config,
 };
 use orgize::export::HtmlHandler;
-use orgize::{Element, Event, Org, ParseConfig};
+use orgize::{elements::FnRef, Element, Event, Org, ParseConfig};
 use serde_json::value::{from_value, to_value, Value};
+use slugify::slugify;
 use std::collections::HashMap;
+use std::io::Write;
 use std::path::PathBuf;
 use tera::{Function as TeraFn, Result as TeraResult};
 
@@ -41,15 +44,87 @@ impl Render {
         }
     }
 
-    pub fn render_entire_file(&self, update_level: Option<i8>) -> String {
-        let parsed = Org::parse_custom(
-            &self.original_org,
+    /// sidenotes is true when the file's footnotes are written as sidenotes,
+    /// with `#+firn_footnotes` or else `file: footnotes`.
+    fn sidenotes(&self) -> bool {
+        let mode = self
+            .front_matter
+            .firn_footnotes
+            .as_deref()
+            .unwrap_or(&self.user_config.file.footnotes);
+        mode == "sidenotes"
+    }
+
+    /// parse parses the file without its footnote definitions, which are
+    /// returned by label (see footnotes.rs).
+    fn parse(&self) -> (Org<'static>, HashMap<String, String>) {
+        let (org, definitions) = footnotes::split_definitions(&self.original_org);
+        let parsed = Org::parse_string_custom(
+            org,
             &ParseConfig {
                 todo_keywords: (self.user_config.file.todo_keywords.clone(), vec![]),
             },
         );
+        (parsed, footnotes::definitions_map(definitions))
+    }
+
+    /// render_footnote renders a footnote's text, without a document or section around it.
+    /// `inline` leaves out paragraphs too (putting a <br> between them), for sidenotes.
+    fn render_footnote(&self, text: &str, inline: bool) -> String {
         let mut wr = Vec::new();
         let mut handler = MyHtmlHandler::default();
+        for event in Org::parse(text).iter() {
+            match event {
+                Event::Start(Element::Document { .. })
+                | Event::End(Element::Document { .. })
+                | Event::Start(Element::Section)
+                | Event::End(Element::Section) => {}
+                Event::Start(Element::Paragraph { .. }) if inline => {
+                    if !wr.is_empty() {
+                        write!(wr, "<br>").unwrap();
+                    }
+                }
+                Event::End(Element::Paragraph { .. }) if inline => {}
+                Event::Start(Element::Link(link)) => html::write_link(
+                    link,
+                    &mut handler,
+                    &mut wr,
+                    self.base_url.clone(),
+                    self.file_path.clone(),
+                ),
+                Event::Start(el) => handler.start(&mut wr, el).unwrap(),
+                Event::End(el) => handler.end(&mut wr, el).unwrap(),
+            }
+        }
+        String::from_utf8(wr).unwrap()
+    }
+
+    /// write_fn_ref writes a footnote reference, numbering it in `footnotes`.
+    /// Inline footnotes bring their own text; others are looked up in `definitions`.
+    fn write_fn_ref(
+        &self,
+        fn_ref: &FnRef,
+        definitions: &HashMap<String, String>,
+        footnotes: &mut Footnotes,
+        wr: &mut Vec<u8>,
+    ) {
+        let inline = footnotes.is_sidenotes();
+        let (number, count) = footnotes.reference(&fn_ref.label, || {
+            let text = fn_ref
+                .definition
+                .as_deref()
+                .or_else(|| definitions.get(&fn_ref.label[..]).map(|d| &d[..]))
+                .unwrap_or_default();
+            self.render_footnote(text, inline)
+        });
+        footnotes.write_ref(wr, number, count).unwrap()
+    }
+
+    pub fn render_entire_file(&self, update_level: Option<i8>) -> String {
+        let (parsed, definitions) = self.parse();
+        let mut footnotes = Footnotes::new(self.sidenotes(), "");
+        let mut wr = Vec::new();
+        let mut handler = MyHtmlHandler::new(&self.front_matter, &self.user_config);
 
         // here we manually write out html *only when we are in the headlines we want*.
         for event in parsed.iter() {
@@ -65,11 +140,20 @@ impl Render {
                         self.base_url.clone(),
                         self.file_path.clone(),
                     ),
+                    Element::FnRef(fn_ref) => {
+                        self.write_fn_ref(fn_ref, &definitions, &mut footnotes, &mut wr)
+                    }
                     _ => handler.start(&mut wr, el).unwrap(),
                 },
-                Event::End(el) => handler.end(&mut wr, el).unwrap(),
+                Event::End(el) => match el {
+                    Element::Title(title) => {
+                        html::write_title_end(title, &mut handler, &mut wr, update_level)
+                    }
+                    _ => handler.end(&mut wr, el).unwrap(),
+                },
             }
         }
+        footnotes.write_section(&mut wr).unwrap();
         String::from_utf8(wr).unwrap()
     }
 
@@ -78,14 +162,13 @@ impl Render {
         headline: &str,
         update_level: Option<i8>,
     ) -> Result<String, FirnError> {
-        let parsed = Org::parse_custom(
-            &self.original_org,
-            &ParseConfig {
-                todo_keywords: (self.user_config.file.todo_keywords.clone(), vec![]),
-            },
-        );
+        let (parsed, definitions) = self.parse();
+        // a layout can render several headlines, so their footnotes' ids are
+        // prefixed with the headline.
+        let id_prefix = format!("{}-", slugify!(headline));
+        let mut footnotes = Footnotes::new(self.sidenotes(), &id_prefix);
         let mut wr = Vec::new();
-        let mut handler = MyHtmlHandler::default();
+        let mut handler = MyHtmlHandler::new(&self.front_matter, &self.user_config);
         let mut is_writing = false;
         let mut headline_found = false;
         let mut is_writing_level = 0;
@@ -106,6 +189,9 @@ impl Render {
                                 self.base_url.clone(),
                                 self.file_path.clone(),
                             ),
+                            Element::FnRef(fn_ref) => {
+                                self.write_fn_ref(fn_ref, &definitions, &mut footnotes, &mut wr)
+                            }
                             _ => handler.start(&mut wr, el).unwrap(),
                         }
                     }
@@ -113,7 +199,12 @@ impl Render {
 
                 Event::End(el) => {
                     if is_writing {
-                        handler.end(&mut wr, el).unwrap();
+                        match el {
+                            Element::Title(title) => {
+                                html::write_title_end(title, &mut handler, &mut wr, update_level)
+                            }
+                            _ => handler.end(&mut wr, el).unwrap(),
+                        }
                     } else {
                         // A bit hacky, but we meet titles when iterating like so:
                         //  // Start(Text { value: "Notes" })
@@ -140,18 +231,19 @@ impl Render {
         }
 
         if !headline_found {
-            return Err(FirnError::new(
+            Err(FirnError::new(
                 &format!("No headline found for {:?}", &self.front_matter.title),
                 FirnErrorType::HeadlineNotFound,
-            ));
+            ))
         } else {
+            footnotes.write_section(&mut wr).unwrap();
             let as_html = String::from_utf8(wr).unwrap();
             Ok(as_html)
         }
     }
 }
 
-impl<'a> TeraFn for Render {
+impl TeraFn for Render {
     fn call(&self, args: &HashMap<String, Value>) -> TeraResult<Value> {
         let headline = optional_arg!(
             String,
@@ -180,7 +272,7 @@ impl<'a> TeraFn for Render {
             }
         } else {
             // no headline? Return the entire file rendered
-            Ok(to_value(&self.render_entire_file(update_level)).unwrap())
+            Ok(to_value(self.render_entire_file(update_level)).unwrap())
         }
     }
 }
@@ -0,0 +1,296 @@
+use crate::{config::Config, graph, org::OrgFile, util};
+use orgize::export::HtmlEscape;
+use orgize::{Element, Event, Org};
+use serde::Serialize;
+use serde_json::value::{to_value, Value};
+use std::collections::HashMap;
+use tera::{Function as TeraFn, Result as TeraResult};
+
+/// descriptions are cut to about what search engines show.
+const DESCRIPTION_LENGTH: usize = 160;
+
+/// Seo is the metadata a page's `<head>` needs for search engines and link
+/// previews (open graph, twitter cards, and schema.org json-ld).
+#[derive(Debug, Clone, Serialize)]
+pub struct Seo {
+    pub title: String,
+    pub site_name: String,
+    pub canonical_url: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    /// open graph type: "article" for posts, "website" otherwise.
+    pub kind: String,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// first_paragraph returns the plain text of the first paragraph in a file.
+fn first_paragraph(parsed: &Org) -> Option<String> {
+    let mut text = String::new();
+    let mut in_paragraph = false;
+    for event in parsed.iter() {
+        match event {
+            Event::Start(Element::Paragraph { .. }) => in_paragraph = true,
+            Event::End(Element::Paragraph { .. }) => {
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    return Some(text);
+                }
+                in_paragraph = false;
+            }
+            Event::Start(Element::Text { value })
+            | Event::Start(Element::Code { value })
+            | Event::Start(Element::Verbatim { value })
+                if in_paragraph =>
+            {
+                text.push_str(value)
+            }
+            Event::Start(Element::Link(link)) if in_paragraph => {
+                text.push_str(link.desc.as_ref().unwrap_or(&link.path))
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// truncate cuts text to `max` characters, at a word boundary, adding an ellipsis.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    for word in text.split_whitespace() {
+        if out.chars().count() + word.chars().count() + 1 > max - 1 {
+            break;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out.push('…');
+    out
+}
+
+impl Seo {
+    pub fn new(o: &OrgFile, cfg: &Config) -> Seo {
+        let description = o
+            .front_matter
+            .other
+            .get("description")
+            .cloned()
+            .or_else(|| first_paragraph(&o.parsed))
+            .map(|d| truncate(&d, DESCRIPTION_LENGTH));
+
+        // images can be web urls, or links relative to the file like any other link.
+        let image = o
+            .front_matter
+            .firn_image
+            .clone()
+            .or_else(|| {
+                o.attachments
+                    .iter()
+                    .find(|a| util::org_str_is_img_link(a))
+                    .cloned()
+            })
+            .map(|img| {
+                if img.starts_with("http://") || img.starts_with("https://") {
+                    img
+                } else {
+                    let img = if img.starts_with("file:") {
+                        img
+                    } else {
+                        format!("file:{}", img)
+                    };
+                    util::transform_org_link_to_html(
+                        cfg.base_url.clone(),
+                        img,
+                        o.file_path.clone(),
+                    )
+                }
+            });
+
+        let kind = if o.front_matter.is_post() {
+            "article"
+        } else {
+            "website"
+        };
+
+        Seo {
+            title: o.front_matter.get_title().to_string(),
+            site_name: cfg.user_config.site.title.clone(),
+            canonical_url: o.full_url.clone(),
+            description,
+            image,
+            kind: kind.to_string(),
+            date_published: o.front_matter.date_created.clone(),
+            date_modified: o.front_matter.date_updated.clone(),
+            tags: graph::file_tags(o),
+        }
+    }
+
+    /// json_ld is the schema.org `Article` for posts.
+    pub fn json_ld(&self) -> Option<String> {
+        if self.kind != "article" {
+            return None;
+        }
+        let mut article = serde_json::json!({
+            "@context": "https://schema.org",
+            "@type": "Article",
+            "headline": self.title,
+            "url": self.canonical_url,
+            "mainEntityOfPage": self.canonical_url,
+            "publisher": { "@type": "Organization", "name": self.site_name },
+        });
+        let fields = [
+            ("description", &self.description),
+            ("image", &self.image),
+            ("datePublished", &self.date_published),
+            ("dateModified", &self.date_modified),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                article[key] = Value::String(value.clone());
+            }
+        }
+        if !self.tags.is_empty() {
+            article["keywords"] = Value::String(self.tags.join(", "));
+        }
+        // "</" can't appear inside a <script>.
+        Some(article.to_string().replace("</", "<\\/"))
+    }
+
+    /// to_html writes the canonical link and every meta tag, ready for a `<head>`.
+    pub fn to_html(&self) -> String {
+        let mut tags: Vec<(&str, &str, String)> = vec![
+            ("property", "og:title", self.title.clone()),
+            ("property", "og:type", self.kind.clone()),
+            ("property", "og:url", self.canonical_url.clone()),
+            ("property", "og:site_name", self.site_name.clone()),
+        ];
+        if let Some(description) = &self.description {
+            tags.insert(0, ("name", "description", description.clone()));
+            tags.push(("property", "og:description", description.clone()));
+        }
+        if let Some(image) = &self.image {
+            tags.push(("property", "og:image", image.clone()));
+        }
+        if self.kind == "article" {
+            if let Some(date) = &self.date_published {
+                tags.push(("property", "article:published_time", date.clone()));
+            }
+            if let Some(date) = &self.date_modified {
+                tags.push(("property", "article:modified_time", date.clone()));
+            }
+            for tag in &self.tags {
+                tags.push(("property", "article:tag", tag.clone()));
+            }
+        }
+        let card = if self.image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        };
+        tags.push(("name", "twitter:card", card.to_string()));
+        tags.push(("name", "twitter:title", self.title.clone()));
+        if let Some(description) = &self.description {
+            tags.push(("name", "twitter:description", description.clone()));
+        }
+        if let Some(image) = &self.image {
+            tags.push(("name", "twitter:image", image.clone()));
+        }
+
+        let mut out = format!(
+            "<link rel=\"canonical\" href=\"{}\" />\n",
+            HtmlEscape(&self.canonical_url)
+        );
+        for (attr, name, content) in tags {
+            out.push_str(&format!(
+                "<meta {}=\"{}\" content=\"{}\" />\n",
+                attr,
+                name,
+                HtmlEscape(&content)
+            ));
+        }
+        if let Some(json_ld) = self.json_ld() {
+            out.push_str(&format!(
+                "<script type=\"application/ld+json\">{}</script>\n",
+                json_ld
+            ));
+        }
+        out
+    }
+}
+
+/// SeoTags is the `seo_tags()` tera function, which writes out a page's `seo`.
+pub struct SeoTags {
+    seo: Seo,
+}
+
+impl SeoTags {
+    pub fn new(seo: Seo) -> SeoTags {
+        SeoTags { seo }
+    }
+}
+
+impl TeraFn for SeoTags {
+    fn call(&self, _args: &HashMap<String, Value>) -> TeraResult<Value> {
+        Ok(to_value(self.seo.to_html()).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seo(kind: &str) -> Seo {
+        Seo {
+            title: "A \"Post\"".to_string(),
+            site_name: "My Site".to_string(),
+            canonical_url: "https://mysite.com/post.html".to_string(),
+            description: Some("About </script> things.".to_string()),
+            image: None,
+            kind: kind.to_string(),
+            date_published: Some("2022-01-02".to_string()),
+            date_modified: None,
+            tags: vec!["rust".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_first_paragraph() {
+        let parsed = Org::parse(
+            "#+title: Test\n\n* Intro\nThe *first*\n[[https://x.com][para]].\n\nSecond.\n",
+        );
+        assert_eq!(
+            Some("The first para.".to_string()),
+            first_paragraph(&parsed)
+        );
+        assert_eq!(None, first_paragraph(&Org::parse("* Empty\n")));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("short", truncate("short", 10));
+        assert_eq!("one two…", truncate("one two three", 10));
+    }
+
+    #[test]
+    fn test_to_html() {
+        let html = seo("article").to_html();
+        assert!(html.starts_with("<link rel=\"canonical\" href=\"https://mysite.com/post.html\" />\n<meta name=\"description\" content=\"About &lt;/script&gt; things.\" />\n"));
+        assert!(html.contains("<meta property=\"og:title\" content=\"A &quot;Post&quot;\" />"));
+        assert!(
+            html.contains("<meta property=\"article:published_time\" content=\"2022-01-02\" />")
+        );
+        assert!(html.contains("<meta name=\"twitter:card\" content=\"summary\" />"));
+        assert!(html.contains("\"@type\":\"Article\""));
+        assert!(html.contains("About <\\/script> things."));
+
+        let html = seo("website").to_html();
+        assert!(!html.contains("article:"));
+        assert!(!html.contains("application/ld+json"));
+    }
+}
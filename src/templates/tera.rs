@@ -1,6 +1,7 @@
 use crate::{config::Config, org::OrgFile, templates, util};
+use std::fs;
 use std::path::Path;
-use tera::Tera;
+use tera::{Context, Tera};
 
 /// setup_tera
 /// Registers everything we need and returns the tera instance.
@@ -16,7 +17,7 @@ use tera::Tera;
 /// file. So... refactor later when I better understand Rust things. NOTE: It
 /// might be possible that I could just connect the tera template trait impl to
 /// the original structs for OrgFile?
-pub fn setup(cfg: &Config, org_file: &OrgFile) -> Tera {
+pub fn setup(cfg: &Config, org_file: &OrgFile, seo: templates::Seo) -> Tera {
     let mut tera = cfg.tera.clone();
     // Register our functions.
     tera.register_function("render", templates::Render::new(org_file, cfg));
@@ -24,8 +25,11 @@ pub fn setup(cfg: &Config, org_file: &OrgFile) -> Tera {
         "toc",
         templates::Toc::new(org_file, cfg.user_config.clone()),
     );
-    // More templates to come later...
-    // tera.register_function("logbook", templates::Render::new(&org_file));
+    tera.register_function("seo_tags", templates::SeoTags::new(seo));
+    tera.register_function(
+        "logbook",
+        templates::LogbookFn::new(&org_file.logbook, &cfg.logbook, &cfg.user_config.site.url),
+    );
     tera
 }
 
@@ -45,3 +49,61 @@ pub fn load_templates(dir_templates: &Path) -> Tera {
     }
     tera
 }
+
+/// site_context is the context every page starts from, with the site-wide
+/// data (sitemap, tags, posts, ...) serialized once per build rather than once
+/// per page. Tera contexts own their values, so each page still gets a copy:
+/// values that none of the site's templates mention are left out.
+pub fn site_context(cfg: &Config) -> Context {
+    let sources: Vec<String> = util::load_files(&cfg.dir_templates, "**/*.html")
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .collect();
+    let used = |name: &str| sources.iter().any(|source| mentions(source, name));
+
+    let mut ctx = Context::new();
+    ctx.insert("config", &cfg.user_config);
+    if used("tags") {
+        ctx.insert("tags", &cfg.tags_list);
+    }
+    if used("sitemap") {
+        ctx.insert("sitemap", &cfg.sitemap);
+    }
+    if used("sitemap_tree") {
+        ctx.insert("sitemap_tree", &cfg.sitemap_tree);
+    }
+    if used("posts") {
+        ctx.insert("posts", &cfg.posts);
+    }
+    if used("archive") {
+        ctx.insert("archive", &cfg.archive);
+    }
+    if used("agenda") {
+        ctx.insert("agenda", &cfg.agenda);
+    }
+    ctx
+}
+
+/// mentions is true if `name` is in `source` as a whole identifier (ex:
+/// "sitemap" is not in "sitemap_tree").
+fn mentions(source: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    source.match_indices(name).any(|(i, _)| {
+        !source[..i].ends_with(is_ident) && !source[i + name.len()..].starts_with(is_ident)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions() {
+        let source = "{% for p in posts %}{{ macros::nav_tree(nodes=sitemap_tree) }}";
+        assert!(mentions(source, "posts"));
+        assert!(mentions(source, "sitemap_tree"));
+        assert!(!mentions(source, "sitemap"));
+        assert!(!mentions(source, "tree"));
+        assert!(!mentions(source, "agenda"));
+    }
+}
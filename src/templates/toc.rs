@@ -1,4 +1,4 @@
-use crate::org::OrgFile;
+use crate::{footnotes, org::OrgFile};
 use crate::{user_config, util};
 use orgize::export::{DefaultHtmlHandler, HtmlEscape, HtmlHandler};
 use orgize::Element;
@@ -36,8 +36,8 @@ impl Toc {
         headline_start: Option<String>,
         exclude_root: Option<bool>,
     ) -> String {
-        let parsed = Org::parse_custom(
-            &self.original_org,
+        let parsed = Org::parse_string_custom(
+            footnotes::split_definitions(&self.original_org).0,
             &ParseConfig {
                 todo_keywords: (self.user_config.file.todo_keywords.clone(), vec![]),
             },
@@ -160,7 +160,7 @@ impl TeraFn for Toc {
             }
         }
 
-        Ok(to_value(&self.create_toc(list_type, depth, headline_start, exclude_root)).unwrap())
+        Ok(to_value(self.create_toc(list_type, depth, headline_start, exclude_root)).unwrap())
     }
 }
 
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::util;
+use crate::{footnotes, util};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SiteConfig {
@@ -18,12 +18,319 @@ pub struct TagConfig {
     pub url: String,
     pub org: bool,
     pub firn: bool,
+    /// items per tag page; 0 puts every item on one page.
+    #[serde(default)]
+    pub per_page: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileConfig {
     pub table_of_contents: String,
     pub todo_keywords: Vec<String>,
+    /// wrap every headline in a <details> that can be folded (see `#+firn_fold`).
+    #[serde(default)]
+    pub fold: bool,
+    /// how many levels of headlines start open when folding; 0 folds them all.
+    #[serde(default)]
+    pub fold_open_depth: usize,
+    /// "sidenotes" writes footnotes where they are referenced, for tufte-css style
+    /// sidenotes (see `#+firn_footnotes`); "section" (the default) lists them after the file.
+    #[serde(default)]
+    pub footnotes: String,
+    /// highlight src blocks with syntect's classes (see `firn css syntax`).
+    #[serde(default)]
+    pub src_highlight: bool,
+    /// number the lines of every src block, not only those with `-n`.
+    #[serde(default)]
+    pub src_line_numbers: bool,
+    /// caption src blocks with their `:file` header argument, or else their `#+name`.
+    #[serde(default)]
+    pub src_captions: bool,
+}
+
+/// FeedConfig controls the atom/rss feeds built from `firn_type: post` files.
+/// Every field has a default, so sites without a `feeds` section still build.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct FeedConfig {
+    pub enabled: bool,
+    /// the feeds' author, which atom requires; empty uses the site's title.
+    pub author: String,
+    /// max number of items in a feed; 0 means no limit.
+    pub limit: usize,
+    /// "full" renders the entire file, "summary" renders only `summary_headline`.
+    pub content: String,
+    pub summary_headline: String,
+    pub atom_path: String,
+    pub rss_path: String,
+    /// create an atom and rss feed for every tag in the tags map.
+    pub tag_feeds: bool,
+    /// folder (relative to the site root) that per-tag feeds are written to.
+    pub tag_feeds_url: String,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            enabled: true,
+            author: String::new(),
+            limit: 20,
+            content: "full".to_string(),
+            summary_headline: "Summary".to_string(),
+            atom_path: "feed.xml".to_string(),
+            rss_path: "rss.xml".to_string(),
+            tag_feeds: true,
+            tag_feeds_url: "feeds/".to_string(),
+        }
+    }
+}
+
+/// CalendarConfig controls the iCalendar (.ics) file of scheduled, deadline
+/// and timestamped headlines.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CalendarConfig {
+    pub enabled: bool,
+    pub path: String,
+    /// "file" or "tag" also writes a calendar per file or per tag; "none" (the default) doesn't.
+    pub split: String,
+    /// folder (relative to the site root) that split calendars are written to.
+    pub split_url: String,
+}
+
+impl Default for CalendarConfig {
+    fn default() -> Self {
+        CalendarConfig {
+            enabled: false,
+            path: "calendar.ics".to_string(),
+            split: "none".to_string(),
+            split_url: "calendars/".to_string(),
+        }
+    }
+}
+
+/// CrawlerConfig controls the sitemap.xml and robots.txt written to the _site folder.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CrawlerConfig {
+    pub sitemap: bool,
+    pub robots: bool,
+    /// adds a `Disallow` rule for every folder in site > ignored_directories.
+    pub disallow_ignored_directories: bool,
+    /// extra paths (relative to the site root) to disallow.
+    pub disallow: Vec<String>,
+}
+
+impl Default for CrawlerConfig {
+    fn default() -> Self {
+        CrawlerConfig {
+            sitemap: true,
+            robots: true,
+            disallow_ignored_directories: true,
+            disallow: Vec::new(),
+        }
+    }
+}
+
+/// SearchConfig controls the json search index written to the _site folder.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub enabled: bool,
+    /// where the index is written to, relative to the site root.
+    pub path: String,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            enabled: true,
+            path: "search.json".to_string(),
+        }
+    }
+}
+
+/// GraphConfig controls the link graph exports, and the `local_graph` of each page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub json: bool,
+    pub dot: bool,
+    /// how many links away from a page its `local_graph` reaches.
+    pub local_graph_depth: usize,
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        GraphConfig {
+            json: true,
+            dot: false,
+            local_graph_depth: 1,
+        }
+    }
+}
+
+/// DataApiConfig enables writing a json file alongside every html file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DataApiConfig {
+    pub enabled: bool,
+    /// where the sitemap is written to, relative to the site root.
+    pub index_path: String,
+}
+
+impl Default for DataApiConfig {
+    fn default() -> Self {
+        DataApiConfig {
+            enabled: false,
+            index_path: "index.json".to_string(),
+        }
+    }
+}
+
+/// PostsConfig controls the paginated listing of `firn_type: post` files.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PostsConfig {
+    /// where the listing is written, relative to the site root.
+    pub url: String,
+    /// the layout the listing is rendered with; no layout, no listing.
+    pub layout: String,
+    /// posts per page; 0 puts every post on one page.
+    pub per_page: usize,
+}
+
+impl Default for PostsConfig {
+    fn default() -> Self {
+        PostsConfig {
+            url: "blog/".to_string(),
+            layout: "posts".to_string(),
+            per_page: 10,
+        }
+    }
+}
+
+/// ArchiveConfig controls the year and month archive pages.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// where the archive is written, relative to the site root.
+    pub url: String,
+    /// the layout the archive is rendered with; no layout, no archive pages.
+    pub layout: String,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig {
+            url: "archive/".to_string(),
+            layout: "archive".to_string(),
+        }
+    }
+}
+
+/// AgendaConfig controls the agenda page of todo headlines.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AgendaConfig {
+    /// where the page is written, relative to the site root.
+    pub path: String,
+    /// the layout the page is rendered with; no layout, no agenda page.
+    pub layout: String,
+}
+
+impl Default for AgendaConfig {
+    fn default() -> Self {
+        AgendaConfig {
+            path: "agenda.html".to_string(),
+            layout: "agenda".to_string(),
+        }
+    }
+}
+
+/// GeminiConfig controls the gemtext (.gmi) output, written next to _site.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct GeminiConfig {
+    pub enabled: bool,
+    /// your capsule's url, used for links instead of site.url.
+    pub url: String,
+    /// where the .gmi files are written, relative to _firn.
+    pub path: String,
+}
+
+impl Default for GeminiConfig {
+    fn default() -> Self {
+        GeminiConfig {
+            enabled: false,
+            url: "gemini://localhost".to_string(),
+            path: "_gemini".to_string(),
+        }
+    }
+}
+
+/// BookConfig controls `firn export book`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BookConfig {
+    /// files (by title or path) to put in the book, in order.
+    pub files: Vec<String>,
+    /// the layout the book is rendered with, if the site has one.
+    pub layout: String,
+    /// the language of `firn export epub`'s books (ex: "en", "fr").
+    pub language: String,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        BookConfig {
+            files: vec![],
+            layout: "book".to_string(),
+            language: "en".to_string(),
+        }
+    }
+}
+
+/// LogbookConfig controls the site-wide logbook page.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogbookConfig {
+    /// where the page is written, relative to the site root.
+    pub path: String,
+    /// the layout the page is rendered with; no layout, no logbook page.
+    pub layout: String,
+}
+
+impl Default for LogbookConfig {
+    fn default() -> Self {
+        LogbookConfig {
+            path: "logbook.html".to_string(),
+            layout: "logbook".to_string(),
+        }
+    }
+}
+
+/// PropertiesConfig controls how drawers are rendered in files with `#+firn_properties`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PropertiesConfig {
+    /// property keys (case insensitive) left out of a headline's properties.
+    pub hidden: Vec<String>,
+    /// drawers (other than PROPERTIES) that are rendered; the rest are hidden.
+    pub drawers: Vec<String>,
+}
+
+impl Default for PropertiesConfig {
+    fn default() -> Self {
+        PropertiesConfig {
+            hidden: vec![
+                "ID".to_string(),
+                "CUSTOM_ID".to_string(),
+                "VISIBILITY".to_string(),
+            ],
+            drawers: vec![],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -31,6 +338,32 @@ pub struct UserConfig {
     pub site: SiteConfig,
     pub file: FileConfig,
     pub tags: TagConfig,
+    #[serde(default)]
+    pub feeds: FeedConfig,
+    #[serde(default)]
+    pub calendar: CalendarConfig,
+    #[serde(default)]
+    pub crawlers: CrawlerConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub graph: GraphConfig,
+    #[serde(default)]
+    pub data_api: DataApiConfig,
+    #[serde(default)]
+    pub posts: PostsConfig,
+    #[serde(default)]
+    pub gemini: GeminiConfig,
+    #[serde(default)]
+    pub book: BookConfig,
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    #[serde(default)]
+    pub agenda: AgendaConfig,
+    #[serde(default)]
+    pub logbook: LogbookConfig,
+    #[serde(default)]
+    pub properties: PropertiesConfig,
 }
 
 impl UserConfig {
@@ -51,5 +384,22 @@ impl UserConfig {
             println!("Error in config.yaml: site > sass must be of value 'sass' or 'scss'");
             util::exit();
         }
+        if self.feeds.content != "full" && self.feeds.content != "summary" {
+            println!("Error in config.yaml: feeds > content must be of value 'full' or 'summary'");
+            util::exit();
+        }
+        if !["none", "file", "tag"].contains(&&self.calendar.split[..]) {
+            println!(
+                "Error in config.yaml: calendar > split must be of value 'none', 'file' or 'tag'"
+            );
+            util::exit();
+        }
+        if !self.file.footnotes.is_empty() && !footnotes::MODES.contains(&&self.file.footnotes[..])
+        {
+            println!(
+                "Error in config.yaml: file > footnotes must be of value 'section' or 'sidenotes'"
+            );
+            util::exit();
+        }
     }
 }
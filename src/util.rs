@@ -34,6 +34,11 @@ pub fn transform_org_link_to_html(
 
     // -- handle different types of links.
 
+    // <0> -- It's a headline in the same file (`[[*Heading]]`).
+    if let Some(headline) = link_path.strip_prefix('*') {
+        return headline_href("", headline);
+    }
+
     // <1> -- It's a local org file.
     if is_local_org_file(&link_path) {
         // link_path = clean_file_link(link_path);
@@ -102,17 +107,49 @@ pub fn get_template(tera: &Tera, template: &str) -> Result<String, FirnError> {
     Ok(default_template_name)
 }
 
+/// headline ids are their raw titles (see html.rs), which can contain spaces.
+pub fn headline_href(base: &str, raw: &str) -> String {
+    format!("{}#{}", base, raw.replace(' ', "%20"))
+}
+
 pub fn path_to_string(p: &Path) -> String {
     p.display().to_string()
 }
 
+/// Formats a front matter timestamp (ex: `date_created_ts`) with a chrono format string.
+pub fn format_timestamp(ts: i64, fmt: &str) -> String {
+    chrono::NaiveDateTime::from_timestamp(ts, 0)
+        .format(fmt)
+        .to_string()
+}
+
+/// Parses a `--now` date (`2022-03-01`, or `2022-03-01 18:00`) into a timestamp
+/// comparable with `date_created_ts`. A date on its own means the end of that day.
+pub fn parse_now(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return Ok(dt.timestamp());
+    }
+    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|d| d.and_hms(23, 59, 59).timestamp())
+        .map_err(|_| format!("{:?} is not a date like 2022-03-01 or 2022-03-01 18:00", s))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_parse_now() {
+        assert_eq!(Ok(1646179199), parse_now("2022-03-01"));
+        assert_eq!(Ok(1646157600), parse_now("2022-03-01 18:00"));
+        assert!(parse_now("March").is_err());
+    }
+
     #[test]
     fn test_is_local_file_link() {
-        assert_eq!(true, is_local_file_link("file:assimil.org"));
-        assert_eq!(false, is_local_file_link("https://theiceshelf.com"));
+        assert!(is_local_file_link("file:assimil.org"));
+        assert!(!is_local_file_link("https://theiceshelf.com"));
     }
 
     #[test]
@@ -158,6 +195,15 @@ mod tests {
             )
         );
 
+        assert_eq!(
+            "#Read%20more",
+            transform_org_link_to_html(
+                base_url.clone(),
+                "*Read more".to_string(),
+                "/Users/pi/firnsite/myfile.org".into()
+            )
+        );
+
         assert_eq!(
             "https://mysite.com/nested/parent_file.html",
             transform_org_link_to_html(